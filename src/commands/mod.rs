@@ -0,0 +1,196 @@
+// src/commands/mod.rs
+mod move_note;
+mod mutation;
+mod publish;
+mod tag;
+mod template;
+mod user;
+mod workspace;
+
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+use crate::config::{ConfigFile, WorkspaceAction};
+
+pub use mutation::Mutation;
+
+/// Returns what the `%%` commands of a document would do, without doing it.
+pub const PREVIEW_COMMAND: &str = "notemancy.previewCommands";
+/// Runs the `%%` commands of a document and removes their lines.
+pub const RUN_COMMAND: &str = "notemancy.runCommands";
+/// Reverts the vault mutations of the last `%%` or workspace commands run.
+pub const UNDO_COMMAND: &str = "notemancy.commands.undo";
+
+/// The commands advertised in `execute_command_provider`.
+pub fn commands() -> Vec<String> {
+    [PREVIEW_COMMAND, RUN_COMMAND, UNDO_COMMAND]
+        .iter()
+        .map(|c| c.to_string())
+        .collect()
+}
+
+/// What a command needs to know about the note it is written in.
+#[derive(Debug, Clone, Copy)]
+pub struct CommandContext<'a> {
+    pub vault_dir: &'a Path,
+    /// The note containing the command.
+    pub note: &'a Path,
+    /// Workspaces of the vault, when they have been listed.
+    pub workspaces: Option<&'a [String]>,
+}
+
+/// A `%%` command. Commands validate their own arguments and plan the mutations
+/// they would perform; planning never touches the vault, so the same plan serves
+/// previews, execution and undo.
+pub trait CustomCommand: Send + Sync {
+    /// The name written after `%%`.
+    fn name(&self) -> &str;
+
+    /// Placeholders for the arguments, shown in hover, e.g. `workspace_name`.
+    fn usage(&self) -> &str;
+
+    fn description(&self) -> &str;
+
+    /// How many arguments the command takes.
+    fn arity(&self) -> RangeInclusive<usize> {
+        1..=1
+    }
+
+    /// Candidates for the argument at `index`.
+    fn complete(&self, _index: usize, _ctx: &CommandContext) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Checks arguments against the vault, once their number is known to be right.
+    /// Problems are returned with the index of the offending argument.
+    fn validate(&self, _args: &[&str], _ctx: &CommandContext) -> Vec<(usize, String)> {
+        Vec::new()
+    }
+
+    /// The mutations the command would perform, without performing them.
+    fn plan(&self, args: &[&str], ctx: &CommandContext) -> Result<Vec<Mutation>, String>;
+}
+
+/// Checks the number of arguments given to a command.
+pub fn check_arity(command: &dyn CustomCommand, count: usize) -> Result<(), String> {
+    let arity = command.arity();
+    if arity.contains(&count) {
+        return Ok(());
+    }
+    let expected = match (*arity.start(), *arity.end()) {
+        (0, 0) => "no arguments".to_string(),
+        (1, 1) => format!("one argument ({})", command.usage()),
+        (min, max) if min == max => format!("{} arguments ({})", min, command.usage()),
+        (min, _) => format!("at least {} arguments ({})", min, command.usage()),
+    };
+    Err(format!("'%%{}' takes {}", command.name(), expected))
+}
+
+/// The commands available in a document: the built-in ones and those defined in
+/// config.yaml.
+pub struct CommandRegistry {
+    commands: Vec<Box<dyn CustomCommand>>,
+}
+
+impl CommandRegistry {
+    pub fn builtin() -> Self {
+        Self {
+            commands: vec![
+                Box::new(workspace::WorkspaceCommand::new(
+                    "nw",
+                    WorkspaceAction::CreateWorkspace,
+                )),
+                Box::new(workspace::WorkspaceCommand::new(
+                    "atw",
+                    WorkspaceAction::AppendToWorkspace,
+                )),
+                Box::new(workspace::WorkspaceCommand::new(
+                    "dfw",
+                    WorkspaceAction::RemoveFromWorkspace,
+                )),
+                Box::new(tag::TagCommand),
+                Box::new(move_note::MoveCommand),
+                Box::new(publish::PublishCommand),
+                Box::new(template::TemplateCommand),
+            ],
+        }
+    }
+
+    /// The built-in commands plus the user-defined ones. A user-defined command
+    /// never replaces a built-in one; config validation reports the clash.
+    pub fn with_config(config: Option<&ConfigFile>) -> Self {
+        let mut registry = Self::builtin();
+        for (name, command) in config.into_iter().flat_map(|c| &c.commands) {
+            if registry.get(name).is_none() {
+                registry
+                    .commands
+                    .push(Box::new(user::UserCommand::new(name, command)));
+            }
+        }
+        registry
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn CustomCommand> {
+        self.commands
+            .iter()
+            .find(|c| c.name() == name)
+            .map(|c| c.as_ref())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &dyn CustomCommand> {
+        self.commands.iter().map(|c| c.as_ref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{UserAction, UserCommandConfig};
+
+    fn shell(script: &str) -> UserCommandConfig {
+        UserCommandConfig {
+            description: Some(format!("Runs {}", script)),
+            action: UserAction::Shell {
+                shell: script.to_string(),
+            },
+        }
+    }
+
+    #[test]
+    fn arity_errors_describe_the_expected_arguments() {
+        let registry = CommandRegistry::builtin();
+        let tag = registry.get("tag").unwrap();
+        assert!(check_arity(tag, 3).is_ok());
+        assert_eq!(
+            check_arity(tag, 0).unwrap_err(),
+            "'%%tag' takes at least 1 arguments (tag_name...)"
+        );
+        let move_note = registry.get("move").unwrap();
+        assert!(check_arity(move_note, 1).is_ok());
+        assert_eq!(
+            check_arity(move_note, 2).unwrap_err(),
+            "'%%move' takes one argument (folder)"
+        );
+    }
+
+    #[test]
+    fn user_commands_never_replace_built_in_ones() {
+        let config = ConfigFile {
+            vaults: Vec::new(),
+            default_vault: String::new(),
+            commands: [("tag", shell("tag.sh")), ("deploy", shell("deploy.sh"))]
+                .into_iter()
+                .map(|(name, command)| (name.to_string(), command))
+                .collect(),
+        };
+        let registry = CommandRegistry::with_config(Some(&config));
+        let tag = registry.get("tag").unwrap();
+        assert_eq!(tag.description(), "Add tags to the note's frontmatter.");
+        assert_eq!(
+            registry.get("deploy").unwrap().description(),
+            "Runs deploy.sh"
+        );
+        assert_eq!(registry.iter().filter(|c| c.name() == "tag").count(), 1);
+        assert!(CommandRegistry::with_config(None).get("deploy").is_none());
+    }
+}
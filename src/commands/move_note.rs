@@ -0,0 +1,132 @@
+// src/commands/move_note.rs
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use super::{CommandContext, CustomCommand, Mutation};
+
+/// `%%move folder`: moves the note to a folder of the vault.
+pub struct MoveCommand;
+
+impl CustomCommand for MoveCommand {
+    fn name(&self) -> &str {
+        "move"
+    }
+
+    fn usage(&self) -> &str {
+        "folder"
+    }
+
+    fn description(&self) -> &str {
+        "Move the note to a folder of the vault, relative to the vault root."
+    }
+
+    fn complete(&self, _index: usize, ctx: &CommandContext) -> Vec<String> {
+        let mut folders = Vec::new();
+        collect_folders(ctx.vault_dir, ctx.vault_dir, &mut folders);
+        folders.sort();
+        folders
+    }
+
+    fn validate(&self, args: &[&str], ctx: &CommandContext) -> Vec<(usize, String)> {
+        match destination(args[0], ctx) {
+            Ok(to) if to.exists() => vec![(0, format!("{} already exists", to.display()))],
+            Ok(_) => Vec::new(),
+            Err(e) => vec![(0, e)],
+        }
+    }
+
+    fn plan(&self, args: &[&str], ctx: &CommandContext) -> Result<Vec<Mutation>, String> {
+        let to = destination(args[0], ctx)?;
+        if to == ctx.note {
+            return Ok(Vec::new());
+        }
+        Ok(vec![Mutation::MoveNote {
+            from: ctx.note.to_path_buf(),
+            to,
+        }])
+    }
+}
+
+/// The new path of the note. The folder must stay inside the vault.
+fn destination(folder: &str, ctx: &CommandContext) -> Result<PathBuf, String> {
+    let folder = Path::new(folder);
+    if !folder
+        .components()
+        .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+    {
+        return Err(format!(
+            "Folder '{}' must be a path inside the vault",
+            folder.display()
+        ));
+    }
+    let file_name = ctx.note.file_name().ok_or("The note has no file name")?;
+    Ok(ctx.vault_dir.join(folder).join(file_name))
+}
+
+/// Folders of the vault relative to its root, skipping hidden ones.
+fn collect_folders(root: &Path, dir: &Path, folders: &mut Vec<String>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let hidden = entry.file_name().to_string_lossy().starts_with('.');
+        if hidden || !path.is_dir() {
+            continue;
+        }
+        if let Ok(relative) = path.strip_prefix(root) {
+            folders.push(relative.to_string_lossy().into_owned());
+        }
+        collect_folders(root, &path, folders);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context<'a>(vault_dir: &'a Path, note: &'a Path) -> CommandContext<'a> {
+        CommandContext {
+            vault_dir,
+            note,
+            workspaces: None,
+        }
+    }
+
+    #[test]
+    fn destination_keeps_the_note_name_inside_the_vault() {
+        let note = Path::new("/vault/inbox/idea.md");
+        let ctx = context(Path::new("/vault"), note);
+        assert_eq!(
+            destination("archive/2024", &ctx).unwrap(),
+            Path::new("/vault/archive/2024/idea.md")
+        );
+        assert_eq!(
+            destination("./archive", &ctx).unwrap(),
+            Path::new("/vault/archive/idea.md")
+        );
+    }
+
+    #[test]
+    fn destination_rejects_folders_outside_the_vault() {
+        let note = Path::new("/vault/idea.md");
+        let ctx = context(Path::new("/vault"), note);
+        assert!(destination("..", &ctx).is_err());
+        assert!(destination("a/../../x", &ctx).is_err());
+        assert!(destination("/etc", &ctx).is_err());
+    }
+
+    #[test]
+    fn moving_to_the_current_folder_plans_nothing() {
+        let note = Path::new("/vault/inbox/idea.md");
+        let ctx = context(Path::new("/vault"), note);
+        assert!(MoveCommand.plan(&["inbox"], &ctx).unwrap().is_empty());
+        assert_eq!(
+            MoveCommand.plan(&["done"], &ctx).unwrap(),
+            [Mutation::MoveNote {
+                from: note.to_path_buf(),
+                to: PathBuf::from("/vault/done/idea.md"),
+            }]
+        );
+    }
+}
@@ -0,0 +1,274 @@
+// src/commands/mutation.rs
+use notemancy_core::workspaces::crud;
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tower_lsp::lsp_types::{RenameFile, Url};
+
+use crate::handlers::frontmatter;
+
+/// A change a `%%` command makes, either to the vault or to the document the
+/// command is written in. Document edits are applied when the command line is
+/// removed; vault mutations are applied by the server afterwards.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum Mutation {
+    CreateWorkspace {
+        workspace: String,
+        note: PathBuf,
+    },
+    AppendToWorkspace {
+        workspace: String,
+        note: PathBuf,
+    },
+    RemoveFromWorkspace {
+        workspace: String,
+        note: PathBuf,
+    },
+    /// Moves the note; the editor does this so the open buffer follows the file.
+    MoveNote {
+        from: PathBuf,
+        to: PathBuf,
+    },
+    /// Runs a user-defined shell command in the vault directory.
+    RunShell {
+        script: String,
+        args: Vec<String>,
+        note: PathBuf,
+        vault: PathBuf,
+    },
+    /// Adds a tag to the document's frontmatter.
+    AddTag {
+        tag: String,
+    },
+    /// Sets a frontmatter key of the document to a YAML value.
+    SetFrontmatter {
+        key: String,
+        value: String,
+    },
+    /// Inserts the contents of a template in place of the command line.
+    InsertTemplate {
+        template: PathBuf,
+    },
+}
+
+impl Mutation {
+    /// Whether the mutation only changes the document the command is written in.
+    pub fn is_document_edit(&self) -> bool {
+        matches!(
+            self,
+            Mutation::AddTag { .. }
+                | Mutation::SetFrontmatter { .. }
+                | Mutation::InsertTemplate { .. }
+        )
+    }
+
+    /// A one-line description for previews and messages.
+    pub fn describe(&self) -> String {
+        match self {
+            Mutation::CreateWorkspace { workspace, note } => {
+                format!("Create workspace '{}' with {}", workspace, note.display())
+            }
+            Mutation::AppendToWorkspace { workspace, note } => {
+                format!("Add {} to workspace '{}'", note.display(), workspace)
+            }
+            Mutation::RemoveFromWorkspace { workspace, note } => {
+                format!("Remove {} from workspace '{}'", note.display(), workspace)
+            }
+            Mutation::MoveNote { from, to } => {
+                format!("Move {} to {}", from.display(), to.display())
+            }
+            Mutation::RunShell { script, args, .. } if args.is_empty() => {
+                format!("Run `{}`", script)
+            }
+            Mutation::RunShell { script, args, .. } => {
+                format!("Run `{}` with {}", script, args.join(" "))
+            }
+            Mutation::AddTag { tag } => format!("Add tag '{}'", tag),
+            Mutation::SetFrontmatter { key, value } => format!("Set '{}' to {}", key, value),
+            Mutation::InsertTemplate { template } => {
+                format!("Insert template {}", template.display())
+            }
+        }
+    }
+
+    /// The lines replacing the command line: those of the template for
+    /// `InsertTemplate`, none for every other mutation. They are returned without
+    /// line endings, so the note's own are used between them.
+    pub fn replacement(&self) -> Result<Vec<String>, String> {
+        match self {
+            Mutation::InsertTemplate { template } => fs::read_to_string(template)
+                .map(|text| {
+                    text.trim_end_matches(['\n', '\r'])
+                        .lines()
+                        .map(str::to_string)
+                        .collect()
+                })
+                .map_err(|e| format!("Failed to read template {}: {}", template.display(), e)),
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    /// Applies a frontmatter edit to the document text. Other mutations leave it
+    /// unchanged.
+    pub fn apply_to_text(&self, text: &str) -> String {
+        match self {
+            Mutation::AddTag { tag } => frontmatter::add_list_value(text, "tags", tag),
+            Mutation::SetFrontmatter { key, value } => frontmatter::set_key(text, key, value),
+            _ => text.to_string(),
+        }
+    }
+
+    /// The file rename the editor should perform for `MoveNote`.
+    pub fn rename_file(&self) -> Option<RenameFile> {
+        let Mutation::MoveNote { from, to } = self else {
+            return None;
+        };
+        Some(RenameFile {
+            old_uri: Url::from_file_path(from).ok()?,
+            new_uri: Url::from_file_path(to).ok()?,
+            options: None,
+            annotation_id: None,
+        })
+    }
+
+    /// Applies a vault mutation. Document edits are not applied here.
+    pub fn apply(&self, vault_dir: &Path) -> Result<(), String> {
+        let note_str = |note: &Path| {
+            note.to_str()
+                .map(str::to_string)
+                .ok_or_else(|| "Failed to convert file path to string".to_string())
+        };
+        match self {
+            Mutation::CreateWorkspace { workspace, note } => {
+                crud::create_workspace(vault_dir, workspace, &note_str(note)?)
+                    .map_err(|e| format!("Error creating workspace '{}': {}", workspace, e))
+            }
+            Mutation::AppendToWorkspace { workspace, note } => {
+                crud::append_to_workspace(vault_dir, workspace, &note_str(note)?)
+                    .map_err(|e| format!("Error appending to workspace '{}': {}", workspace, e))
+            }
+            Mutation::RemoveFromWorkspace { workspace, note } => {
+                crud::remove_from_workspace(vault_dir, workspace, &note_str(note)?)
+                    .map_err(|e| format!("Error removing from workspace '{}': {}", workspace, e))
+            }
+            Mutation::MoveNote { from, to } => {
+                if let Some(parent) = to.parent() {
+                    fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+                }
+                fs::rename(from, to).map_err(|e| format!("Error moving {}: {}", from.display(), e))
+            }
+            Mutation::RunShell {
+                script,
+                args,
+                note,
+                vault,
+            } => {
+                let output = Command::new("sh")
+                    .arg("-c")
+                    .arg(script)
+                    .arg("notemancy")
+                    .args(args)
+                    .current_dir(vault)
+                    .env("NOTEMANCY_NOTE", note)
+                    .env("NOTEMANCY_VAULT", vault)
+                    .output()
+                    .map_err(|e| format!("Failed to run `{}`: {}", script, e))?;
+                if output.status.success() {
+                    Ok(())
+                } else {
+                    Err(format!(
+                        "`{}` failed ({}): {}",
+                        script,
+                        output.status,
+                        String::from_utf8_lossy(&output.stderr).trim()
+                    ))
+                }
+            }
+            Mutation::AddTag { .. }
+            | Mutation::SetFrontmatter { .. }
+            | Mutation::InsertTemplate { .. } => Ok(()),
+        }
+    }
+
+    /// The mutation reverting this one, if it can be reverted. Creating a workspace
+    /// is undone by removing the note from it, as notemancy-core cannot delete
+    /// workspaces. Document edits are undone in the editor.
+    pub fn undo(&self) -> Option<Mutation> {
+        match self {
+            Mutation::CreateWorkspace { workspace, note }
+            | Mutation::AppendToWorkspace { workspace, note } => {
+                Some(Mutation::RemoveFromWorkspace {
+                    workspace: workspace.clone(),
+                    note: note.clone(),
+                })
+            }
+            Mutation::RemoveFromWorkspace { workspace, note } => {
+                Some(Mutation::AppendToWorkspace {
+                    workspace: workspace.clone(),
+                    note: note.clone(),
+                })
+            }
+            Mutation::MoveNote { from, to } => Some(Mutation::MoveNote {
+                from: to.clone(),
+                to: from.clone(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn workspace_mutations_undo_each_other() {
+        let (workspace, note) = ("ideas".to_string(), PathBuf::from("/vault/n.md"));
+        let append = Mutation::AppendToWorkspace {
+            workspace: workspace.clone(),
+            note: note.clone(),
+        };
+        let remove = Mutation::RemoveFromWorkspace {
+            workspace: workspace.clone(),
+            note: note.clone(),
+        };
+        let create = Mutation::CreateWorkspace { workspace, note };
+        assert_eq!(append.undo(), Some(remove.clone()));
+        assert_eq!(remove.undo(), Some(append));
+        assert_eq!(create.undo(), Some(remove));
+    }
+
+    #[test]
+    fn moves_undo_by_moving_back() {
+        let move_note = Mutation::MoveNote {
+            from: PathBuf::from("/vault/a/n.md"),
+            to: PathBuf::from("/vault/b/n.md"),
+        };
+        let back = move_note.undo().unwrap();
+        assert_eq!(
+            back,
+            Mutation::MoveNote {
+                from: PathBuf::from("/vault/b/n.md"),
+                to: PathBuf::from("/vault/a/n.md"),
+            }
+        );
+        assert_eq!(back.undo(), Some(move_note));
+    }
+
+    #[test]
+    fn shell_commands_and_document_edits_have_no_undo() {
+        let shell = Mutation::RunShell {
+            script: "echo".to_string(),
+            args: Vec::new(),
+            note: PathBuf::from("/vault/n.md"),
+            vault: PathBuf::from("/vault"),
+        };
+        assert_eq!(shell.undo(), None);
+        let tag = Mutation::AddTag {
+            tag: "idea".to_string(),
+        };
+        assert_eq!(tag.undo(), None);
+    }
+}
@@ -0,0 +1,30 @@
+// src/commands/publish.rs
+use super::{CommandContext, CustomCommand, Mutation};
+
+/// `%%publish`: marks the note for publishing to the vault's publish URL.
+pub struct PublishCommand;
+
+impl CustomCommand for PublishCommand {
+    fn name(&self) -> &str {
+        "publish"
+    }
+
+    fn usage(&self) -> &str {
+        ""
+    }
+
+    fn description(&self) -> &str {
+        "Mark the note for publishing by setting `publish: true` in its frontmatter."
+    }
+
+    fn arity(&self) -> std::ops::RangeInclusive<usize> {
+        0..=0
+    }
+
+    fn plan(&self, _args: &[&str], _ctx: &CommandContext) -> Result<Vec<Mutation>, String> {
+        Ok(vec![Mutation::SetFrontmatter {
+            key: "publish".to_string(),
+            value: "true".to_string(),
+        }])
+    }
+}
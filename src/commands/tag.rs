@@ -0,0 +1,48 @@
+// src/commands/tag.rs
+use super::{CommandContext, CustomCommand, Mutation};
+
+/// `%%tag name...`: adds tags to the note's frontmatter.
+pub struct TagCommand;
+
+impl CustomCommand for TagCommand {
+    fn name(&self) -> &str {
+        "tag"
+    }
+
+    fn usage(&self) -> &str {
+        "tag_name..."
+    }
+
+    fn description(&self) -> &str {
+        "Add tags to the note's frontmatter."
+    }
+
+    fn arity(&self) -> std::ops::RangeInclusive<usize> {
+        1..=usize::MAX
+    }
+
+    fn validate(&self, args: &[&str], _ctx: &CommandContext) -> Vec<(usize, String)> {
+        args.iter()
+            .enumerate()
+            .filter(|(_, tag)| !is_valid_tag(tag.trim_start_matches('#')))
+            .map(|(i, tag)| (i, format!("'{}' is not a valid tag", tag)))
+            .collect()
+    }
+
+    fn plan(&self, args: &[&str], _ctx: &CommandContext) -> Result<Vec<Mutation>, String> {
+        Ok(args
+            .iter()
+            .map(|tag| Mutation::AddTag {
+                tag: tag.trim_start_matches('#').to_string(),
+            })
+            .collect())
+    }
+}
+
+/// Tags use the same characters as inline `#tags`.
+fn is_valid_tag(tag: &str) -> bool {
+    tag.starts_with(|c: char| c.is_ascii_alphabetic())
+        && tag
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '/'))
+}
@@ -0,0 +1,77 @@
+// src/commands/template.rs
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::{CommandContext, CustomCommand, Mutation};
+
+/// Folder of the vault holding the templates.
+const TEMPLATES_DIR: &str = "templates";
+
+/// `%%template name`: inserts `templates/<name>.md` in place of the command.
+pub struct TemplateCommand;
+
+impl CustomCommand for TemplateCommand {
+    fn name(&self) -> &str {
+        "template"
+    }
+
+    fn usage(&self) -> &str {
+        "template_name"
+    }
+
+    fn description(&self) -> &str {
+        "Insert a template from the vault's `templates` folder in place of the command."
+    }
+
+    fn complete(&self, _index: usize, ctx: &CommandContext) -> Vec<String> {
+        let Ok(entries) = fs::read_dir(ctx.vault_dir.join(TEMPLATES_DIR)) else {
+            return Vec::new();
+        };
+        let mut names: Vec<String> = entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "md"))
+            .filter_map(|path| Some(path.file_stem()?.to_string_lossy().into_owned()))
+            .collect();
+        names.sort();
+        names
+    }
+
+    fn validate(&self, args: &[&str], ctx: &CommandContext) -> Vec<(usize, String)> {
+        match template_path(ctx.vault_dir, args[0]) {
+            Ok(path) if path.is_file() => Vec::new(),
+            Ok(_) => vec![(0, format!("Template '{}' does not exist", args[0]))],
+            Err(e) => vec![(0, e)],
+        }
+    }
+
+    fn plan(&self, args: &[&str], ctx: &CommandContext) -> Result<Vec<Mutation>, String> {
+        Ok(vec![Mutation::InsertTemplate {
+            template: template_path(ctx.vault_dir, args[0])?,
+        }])
+    }
+}
+
+fn template_path(vault_dir: &Path, name: &str) -> Result<PathBuf, String> {
+    if name.contains(['/', '\\']) || name.starts_with('.') {
+        return Err(format!("'{}' is not a template name", name));
+    }
+    Ok(vault_dir.join(TEMPLATES_DIR).join(format!("{}.md", name)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn template_names_stay_in_the_templates_folder() {
+        let vault = Path::new("/vault");
+        assert_eq!(
+            template_path(vault, "daily").unwrap(),
+            Path::new("/vault/templates/daily.md")
+        );
+        for name in ["../secret", "a/b", "a\\b", "/etc/passwd", ".hidden"] {
+            assert!(template_path(vault, name).is_err(), "{}", name);
+        }
+    }
+}
@@ -0,0 +1,103 @@
+// src/commands/user.rs
+use std::ops::RangeInclusive;
+
+use super::workspace::{check_workspace, complete_workspace, mutation};
+use super::{CommandContext, CustomCommand, Mutation};
+use crate::config::{UserAction, UserCommandConfig};
+
+/// A command defined in config.yaml.
+pub struct UserCommand {
+    name: String,
+    description: String,
+    action: UserAction,
+}
+
+impl UserCommand {
+    pub fn new(name: &str, config: &UserCommandConfig) -> Self {
+        let description = config
+            .description
+            .clone()
+            .unwrap_or_else(|| match &config.action {
+                UserAction::Shell { shell } => format!("Run `{}`.", shell),
+                UserAction::Workspace { .. } => "A workspace action from config.yaml.".to_string(),
+            });
+        Self {
+            name: name.to_string(),
+            description,
+            action: config.action.clone(),
+        }
+    }
+}
+
+impl CustomCommand for UserCommand {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn usage(&self) -> &str {
+        match &self.action {
+            UserAction::Shell { .. } => "args...",
+            UserAction::Workspace {
+                workspace: Some(_), ..
+            } => "",
+            UserAction::Workspace {
+                workspace: None, ..
+            } => "workspace_name",
+        }
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn arity(&self) -> RangeInclusive<usize> {
+        match &self.action {
+            UserAction::Shell { .. } => 0..=usize::MAX,
+            UserAction::Workspace {
+                workspace: Some(_), ..
+            } => 0..=0,
+            UserAction::Workspace {
+                workspace: None, ..
+            } => 1..=1,
+        }
+    }
+
+    fn complete(&self, _index: usize, ctx: &CommandContext) -> Vec<String> {
+        match &self.action {
+            UserAction::Workspace {
+                action,
+                workspace: None,
+            } => complete_workspace(*action, ctx),
+            _ => Vec::new(),
+        }
+    }
+
+    fn validate(&self, args: &[&str], ctx: &CommandContext) -> Vec<(usize, String)> {
+        match &self.action {
+            UserAction::Workspace {
+                action,
+                workspace: None,
+            } => check_workspace(*action, args[0], ctx)
+                .map(|message| (0, message))
+                .into_iter()
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn plan(&self, args: &[&str], ctx: &CommandContext) -> Result<Vec<Mutation>, String> {
+        let mutation = match &self.action {
+            UserAction::Shell { shell } => Mutation::RunShell {
+                script: shell.clone(),
+                args: args.iter().map(|a| a.to_string()).collect(),
+                note: ctx.note.to_path_buf(),
+                vault: ctx.vault_dir.to_path_buf(),
+            },
+            UserAction::Workspace { action, workspace } => {
+                let workspace = workspace.as_deref().unwrap_or(args[0]);
+                mutation(*action, workspace, ctx)
+            }
+        };
+        Ok(vec![mutation])
+    }
+}
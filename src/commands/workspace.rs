@@ -0,0 +1,84 @@
+// src/commands/workspace.rs
+use super::{CommandContext, CustomCommand, Mutation};
+use crate::config::WorkspaceAction;
+
+/// `%%nw`, `%%atw` and `%%dfw`: notemancy-core workspace actions on the current note.
+pub struct WorkspaceCommand {
+    name: &'static str,
+    action: WorkspaceAction,
+}
+
+impl WorkspaceCommand {
+    pub fn new(name: &'static str, action: WorkspaceAction) -> Self {
+        Self { name, action }
+    }
+}
+
+impl CustomCommand for WorkspaceCommand {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn usage(&self) -> &str {
+        "workspace_name"
+    }
+
+    fn description(&self) -> &str {
+        match self.action {
+            WorkspaceAction::CreateWorkspace => "Create a new workspace and add the current note.",
+            WorkspaceAction::AppendToWorkspace => "Append the current note to the workspace.",
+            WorkspaceAction::RemoveFromWorkspace => "Remove the current note from the workspace.",
+        }
+    }
+
+    fn complete(&self, _index: usize, ctx: &CommandContext) -> Vec<String> {
+        complete_workspace(self.action, ctx)
+    }
+
+    fn validate(&self, args: &[&str], ctx: &CommandContext) -> Vec<(usize, String)> {
+        check_workspace(self.action, args[0], ctx)
+            .map(|message| (0, message))
+            .into_iter()
+            .collect()
+    }
+
+    fn plan(&self, args: &[&str], ctx: &CommandContext) -> Result<Vec<Mutation>, String> {
+        Ok(vec![mutation(self.action, args[0], ctx)])
+    }
+}
+
+/// Existing workspaces, for actions on an existing workspace.
+pub fn complete_workspace(action: WorkspaceAction, ctx: &CommandContext) -> Vec<String> {
+    match action {
+        WorkspaceAction::CreateWorkspace => Vec::new(),
+        _ => ctx.workspaces.unwrap_or_default().to_vec(),
+    }
+}
+
+/// Checks that the workspace exists, or for `CreateWorkspace` that it does not.
+pub fn check_workspace(
+    action: WorkspaceAction,
+    workspace: &str,
+    ctx: &CommandContext,
+) -> Option<String> {
+    let exists = ctx.workspaces?.iter().any(|w| w == workspace);
+    match action {
+        WorkspaceAction::CreateWorkspace if exists => {
+            Some(format!("Workspace '{}' already exists", workspace))
+        }
+        WorkspaceAction::AppendToWorkspace | WorkspaceAction::RemoveFromWorkspace if !exists => {
+            Some(format!("Workspace '{}' does not exist", workspace))
+        }
+        _ => None,
+    }
+}
+
+pub fn mutation(action: WorkspaceAction, workspace: &str, ctx: &CommandContext) -> Mutation {
+    let workspace = workspace.to_string();
+    let note = ctx.note.to_path_buf();
+    match action {
+        WorkspaceAction::CreateWorkspace => Mutation::CreateWorkspace { workspace, note },
+        WorkspaceAction::AppendToWorkspace => Mutation::AppendToWorkspace { workspace, note },
+        WorkspaceAction::RemoveFromWorkspace => Mutation::RemoveFromWorkspace { workspace, note },
+    }
+}
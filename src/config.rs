@@ -0,0 +1,292 @@
+// src/config.rs
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tower_lsp::lsp_types::Url;
+
+use crate::commands::CommandRegistry;
+
+/// Configuration types corresponding to config.yaml.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Vault {
+    pub name: String,
+    pub vault_directory: String,
+    pub publish_url: Option<String>,
+    /// Expected frontmatter keys of the vault's notes.
+    #[serde(default)]
+    pub frontmatter: FrontmatterSchema,
+}
+
+/// Frontmatter keys a vault's notes are expected to use, keyed by name.
+pub type FrontmatterSchema = BTreeMap<String, FieldSchema>;
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct FieldSchema {
+    #[serde(rename = "type", default)]
+    pub kind: FieldType,
+    #[serde(default)]
+    pub required: bool,
+    pub description: Option<String>,
+    /// Allowed values, for a string or for each item of a list.
+    #[serde(rename = "enum", default)]
+    pub values: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldType {
+    #[default]
+    Any,
+    String,
+    Number,
+    Boolean,
+    List,
+    /// A `YYYY-MM-DD` date.
+    Date,
+}
+
+impl FieldType {
+    pub fn name(self) -> &'static str {
+        match self {
+            FieldType::Any => "any",
+            FieldType::String => "string",
+            FieldType::Number => "number",
+            FieldType::Boolean => "boolean",
+            FieldType::List => "list",
+            FieldType::Date => "date",
+        }
+    }
+
+    /// Whether a frontmatter value has this type.
+    pub fn matches(self, value: &serde_yaml::Value) -> bool {
+        use serde_yaml::Value;
+        match self {
+            FieldType::Any => true,
+            FieldType::String => matches!(value, Value::String(_)),
+            FieldType::Number => matches!(value, Value::Number(_)),
+            FieldType::Boolean => matches!(value, Value::Bool(_)),
+            FieldType::List => matches!(value, Value::Sequence(_)),
+            FieldType::Date => value.as_str().is_some_and(|s| {
+                let parts: Vec<&str> = s.split('-').collect();
+                parts.len() == 3
+                    && [4, 2, 2]
+                        .iter()
+                        .zip(&parts)
+                        .all(|(len, p)| p.len() == *len && p.bytes().all(|b| b.is_ascii_digit()))
+            }),
+        }
+    }
+}
+
+/// A `%%` command defined in config.yaml, keyed by its name:
+///
+/// ```yaml
+/// commands:
+///   stage:
+///     description: Stage the note in git
+///     shell: git add "$NOTEMANCY_NOTE"
+///   inbox:
+///     action: append_to_workspace
+///     workspace: inbox
+/// ```
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct UserCommandConfig {
+    pub description: Option<String>,
+    #[serde(flatten)]
+    pub action: UserAction,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum UserAction {
+    /// A `sh` script run in the vault directory, with the note in `$NOTEMANCY_NOTE`,
+    /// the vault in `$NOTEMANCY_VAULT` and the command's arguments in `$1`, `$2`...
+    Shell { shell: String },
+    /// A notemancy-core workspace action. Without a configured workspace, the
+    /// command takes the workspace name as its argument.
+    Workspace {
+        action: WorkspaceAction,
+        workspace: Option<String>,
+    },
+}
+
+/// The notemancy-core workspace operations on the current note.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WorkspaceAction {
+    CreateWorkspace,
+    AppendToWorkspace,
+    RemoveFromWorkspace,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ConfigFile {
+    pub vaults: Vec<Vault>,
+    pub default_vault: String,
+    /// User-defined `%%` commands.
+    #[serde(default)]
+    pub commands: BTreeMap<String, UserCommandConfig>,
+}
+
+/// A problem in config.yaml that does not stop it from parsing.
+#[derive(Debug, Clone)]
+pub struct ConfigProblem {
+    /// Where the offending value is, e.g. `vaults`, `1`, `vault_directory`.
+    pub path: Vec<ConfigKey>,
+    /// Whether the problem is the last key itself rather than its value.
+    pub on_key: bool,
+    pub message: String,
+}
+
+/// A step of the path to a value in config.yaml.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigKey {
+    Field(String),
+    Index(usize),
+}
+
+impl ConfigProblem {
+    fn at_vault(index: usize, field: &str, message: String) -> Self {
+        Self {
+            path: vec![
+                ConfigKey::Field("vaults".to_string()),
+                ConfigKey::Index(index),
+                ConfigKey::Field(field.to_string()),
+            ],
+            on_key: false,
+            message,
+        }
+    }
+}
+
+/// Returns the path of config.yaml inside the NOTEMANCY_CONF_DIR directory.
+pub fn config_path() -> Result<PathBuf, String> {
+    let conf_dir = env::var("NOTEMANCY_CONF_DIR")
+        .map_err(|_| "Environment variable NOTEMANCY_CONF_DIR is not set".to_string())?;
+    Ok(Path::new(&conf_dir).join("config.yaml"))
+}
+
+/// Reads config.yaml, returning its text so problems can be reported against it.
+pub fn read_config() -> Result<(PathBuf, String), String> {
+    let config_path = config_path()?;
+    let config_contents = fs::read_to_string(&config_path)
+        .map_err(|e| format!("Failed to read {}: {}", config_path.display(), e))?;
+    Ok((config_path, config_contents))
+}
+
+impl ConfigFile {
+    pub fn parse(text: &str) -> Result<Self, serde_yaml::Error> {
+        serde_yaml::from_str(text)
+    }
+
+    /// Checks what serde cannot: that the default vault exists, vault names are
+    /// unique, vault directories exist, publish URLs are valid http(s) URLs and
+    /// user-defined commands have usable names.
+    pub fn validate(&self) -> Vec<ConfigProblem> {
+        let mut problems = Vec::new();
+        if !self.vaults.iter().any(|v| v.name == self.default_vault) {
+            problems.push(ConfigProblem {
+                path: vec![ConfigKey::Field("default_vault".to_string())],
+                on_key: false,
+                message: format!("Default vault '{}' is not defined", self.default_vault),
+            });
+        }
+        for (i, vault) in self.vaults.iter().enumerate() {
+            if self.vaults[..i].iter().any(|v| v.name == vault.name) {
+                problems.push(ConfigProblem::at_vault(
+                    i,
+                    "name",
+                    format!("Vault '{}' is defined more than once", vault.name),
+                ));
+            }
+            if !Path::new(&vault.vault_directory).is_dir() {
+                problems.push(ConfigProblem::at_vault(
+                    i,
+                    "vault_directory",
+                    format!(
+                        "Directory '{}' of vault '{}' does not exist",
+                        vault.vault_directory, vault.name
+                    ),
+                ));
+            }
+            if let Some(url) = &vault.publish_url {
+                let valid = Url::parse(url)
+                    .map(|u| u.scheme() == "http" || u.scheme() == "https")
+                    .unwrap_or(false);
+                if !valid {
+                    problems.push(ConfigProblem::at_vault(
+                        i,
+                        "publish_url",
+                        format!(
+                            "Publish URL '{}' of vault '{}' is not a valid http(s) URL",
+                            url, vault.name
+                        ),
+                    ));
+                }
+            }
+        }
+        let builtin = CommandRegistry::builtin();
+        for name in self.commands.keys() {
+            let message =
+                if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+                    format!(
+                        "Command name '{}' may only contain letters, digits and '_'",
+                        name
+                    )
+                } else if builtin.get(name).is_some() {
+                    format!("Command '{}' would replace the built-in command", name)
+                } else {
+                    continue;
+                };
+            problems.push(ConfigProblem {
+                path: vec![
+                    ConfigKey::Field("commands".to_string()),
+                    ConfigKey::Field(name.clone()),
+                ],
+                on_key: true,
+                message,
+            });
+        }
+        problems
+    }
+
+    /// Returns the vault named by `default_vault`.
+    pub fn default_vault(&self) -> Result<&Vault, String> {
+        self.vaults
+            .iter()
+            .find(|v| v.name == self.default_vault)
+            .ok_or_else(|| format!("Default vault '{}' not found in config", self.default_vault))
+    }
+
+    /// Returns the vault whose directory contains `path`. When vaults are nested the
+    /// innermost one wins.
+    pub fn vault_for_path(&self, path: &Path) -> Option<&Vault> {
+        self.vaults
+            .iter()
+            .filter(|v| path.starts_with(&v.vault_directory))
+            .max_by_key(|v| Path::new(&v.vault_directory).components().count())
+    }
+
+    /// Returns the vaults relevant to a workspace folder: those containing it and
+    /// those inside it.
+    pub fn vaults_for_folder(&self, folder: &Path) -> Vec<&Vault> {
+        self.vaults
+            .iter()
+            .filter(|v| {
+                let dir = Path::new(&v.vault_directory);
+                folder.starts_with(dir) || dir.starts_with(folder)
+            })
+            .collect()
+    }
+
+    /// Returns the directory of the vault containing `path`, falling back to the default vault.
+    pub fn vault_directory_for(&self, path: &Path) -> Result<PathBuf, String> {
+        let vault = match self.vault_for_path(path) {
+            Some(vault) => vault,
+            None => self.default_vault()?,
+        };
+        Ok(PathBuf::from(&vault.vault_directory))
+    }
+}
@@ -0,0 +1,388 @@
+// src/document_store.rs
+use ropey::Rope;
+use std::collections::HashMap;
+use tower_lsp::lsp_types::{Position, Range, TextDocumentContentChangeEvent, Url};
+
+/// An open text document. The contents live in a rope so that incremental
+/// edits from the client only touch the changed region instead of the whole file.
+pub struct Document {
+    rope: Rope,
+    version: i32,
+}
+
+impl Document {
+    pub fn new(text: &str, version: i32) -> Self {
+        Self {
+            rope: Rope::from_str(text),
+            version,
+        }
+    }
+
+    pub fn version(&self) -> i32 {
+        self.version
+    }
+
+    pub fn text(&self) -> String {
+        self.rope.to_string()
+    }
+
+    /// Applies a single content change. A change without a range replaces the whole document.
+    pub fn apply_change(&mut self, change: TextDocumentContentChangeEvent) {
+        match change.range {
+            Some(range) => {
+                let start = self.position_to_char(range.start);
+                let end = self.position_to_char(range.end).max(start);
+                self.rope.remove(start..end);
+                self.rope.insert(start, &change.text);
+            }
+            None => self.rope = Rope::from_str(&change.text),
+        }
+    }
+
+    /// Converts an LSP position (line + UTF-16 code unit offset) into a char index in the rope.
+    /// Positions past the end of a line are clamped to the end of that line.
+    pub fn position_to_char(&self, position: Position) -> usize {
+        let line = position.line as usize;
+        if line >= self.rope.len_lines() {
+            return self.rope.len_chars();
+        }
+        let line_start = self.rope.line_to_char(line);
+        let line_len = line_len_without_break(self.rope.line(line));
+        let line_start_cu = self.rope.char_to_utf16_cu(line_start);
+        let line_end_cu = self.rope.char_to_utf16_cu(line_start + line_len);
+        let target_cu = (line_start_cu + position.character as usize).min(line_end_cu);
+        self.rope.utf16_cu_to_char(target_cu)
+    }
+
+    /// Converts a char index in the rope back into an LSP position.
+    pub fn char_to_position(&self, char_idx: usize) -> Position {
+        let char_idx = char_idx.min(self.rope.len_chars());
+        let line = self.rope.char_to_line(char_idx);
+        let line_start = self.rope.line_to_char(line);
+        let character =
+            self.rope.char_to_utf16_cu(char_idx) - self.rope.char_to_utf16_cu(line_start);
+        Position {
+            line: line as u32,
+            character: character as u32,
+        }
+    }
+
+    /// Returns the position just past the last character of the document.
+    pub fn end_position(&self) -> Position {
+        self.char_to_position(self.rope.len_chars())
+    }
+}
+
+/// Number of chars on a rope line, not counting its trailing line break.
+fn line_len_without_break(line: ropey::RopeSlice) -> usize {
+    let mut len = line.len_chars();
+    if len > 0 && line.char(len - 1) == '\n' {
+        len -= 1;
+    }
+    if len > 0 && line.char(len - 1) == '\r' {
+        len -= 1;
+    }
+    len
+}
+
+/// Store of all documents currently open in the editor, keyed by URI.
+#[derive(Default)]
+pub struct DocumentStore {
+    documents: HashMap<Url, Document>,
+}
+
+impl DocumentStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn open(&mut self, uri: Url, text: &str, version: i32) {
+        self.documents.insert(uri, Document::new(text, version));
+    }
+
+    pub fn close(&mut self, uri: &Url) {
+        self.documents.remove(uri);
+    }
+
+    pub fn get(&self, uri: &Url) -> Option<&Document> {
+        self.documents.get(uri)
+    }
+
+    pub fn text(&self, uri: &Url) -> Option<String> {
+        self.documents.get(uri).map(Document::text)
+    }
+
+    pub fn uris(&self) -> Vec<Url> {
+        self.documents.keys().cloned().collect()
+    }
+
+    pub fn version(&self, uri: &Url) -> Option<i32> {
+        self.documents.get(uri).map(Document::version)
+    }
+
+    /// Applies a batch of content changes in order and bumps the document version.
+    /// Changes carrying a version that is not newer than the stored one are rejected.
+    pub fn apply_changes(
+        &mut self,
+        uri: &Url,
+        version: i32,
+        changes: Vec<TextDocumentContentChangeEvent>,
+    ) -> Result<(), String> {
+        let doc = self
+            .documents
+            .get_mut(uri)
+            .ok_or_else(|| format!("Document {} is not open", uri))?;
+        if version <= doc.version {
+            return Err(format!(
+                "Ignoring stale change for {} (version {} <= {})",
+                uri, version, doc.version
+            ));
+        }
+        for change in changes {
+            doc.apply_change(change);
+        }
+        doc.version = version;
+        Ok(())
+    }
+}
+
+/// Converts a UTF-16 column on a single line into a byte offset into that line.
+/// Columns past the end of the line are clamped to the line length.
+pub fn utf16_to_byte(line: &str, character: u32) -> usize {
+    let mut units = 0u32;
+    for (byte_idx, ch) in line.char_indices() {
+        if units >= character {
+            return byte_idx;
+        }
+        units += ch.len_utf16() as u32;
+    }
+    line.len()
+}
+
+/// Converts a byte offset on a single line into a UTF-16 column. Offsets inside a
+/// character map to the start of that character.
+pub fn byte_to_utf16(line: &str, byte: usize) -> u32 {
+    let mut byte = byte.min(line.len());
+    while !line.is_char_boundary(byte) {
+        byte -= 1;
+    }
+    line[..byte].chars().map(|c| c.len_utf16() as u32).sum()
+}
+
+/// The line ending a document uses: `"\r\n"` if it has any, `"\n"` otherwise.
+/// Edits that rebuild a document from its lines join them with it.
+pub fn line_ending(text: &str) -> &'static str {
+    if text.contains("\r\n") { "\r\n" } else { "\n" }
+}
+
+/// The range of a byte span on a single line, in UTF-16 columns.
+pub fn line_range(line: &str, line_no: u32, bytes: std::ops::Range<usize>) -> Range {
+    Range {
+        start: Position {
+            line: line_no,
+            character: byte_to_utf16(line, bytes.start),
+        },
+        end: Position {
+            line: line_no,
+            character: byte_to_utf16(line, bytes.end),
+        },
+    }
+}
+
+/// Maps byte offsets in a string to LSP positions (UTF-16 columns).
+pub struct LineIndex<'a> {
+    text: &'a str,
+    line_starts: Vec<usize>,
+}
+
+impl<'a> LineIndex<'a> {
+    pub fn new(text: &'a str) -> Self {
+        let mut line_starts = vec![0];
+        line_starts.extend(text.match_indices('\n').map(|(i, _)| i + 1));
+        Self { text, line_starts }
+    }
+
+    pub fn position(&self, offset: usize) -> Position {
+        let offset = offset.min(self.text.len());
+        let line = self.line_starts.partition_point(|&start| start <= offset) - 1;
+        let line_start = self.line_starts[line];
+        Position {
+            line: line as u32,
+            character: byte_to_utf16(&self.text[line_start..], offset - line_start),
+        }
+    }
+
+    pub fn range(&self, range: std::ops::Range<usize>) -> Range {
+        Range {
+            start: self.position(range.start),
+            end: self.position(range.end),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pos(line: u32, character: u32) -> Position {
+        Position { line, character }
+    }
+
+    fn change(start: Position, end: Position, text: &str) -> TextDocumentContentChangeEvent {
+        TextDocumentContentChangeEvent {
+            range: Some(Range { start, end }),
+            range_length: None,
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn utf16_to_byte_handles_multibyte_and_astral_chars() {
+        // 'é' is 2 bytes / 1 unit, '😀' is 4 bytes / 2 units.
+        let line = "aé😀b";
+        assert_eq!(utf16_to_byte(line, 0), 0);
+        assert_eq!(utf16_to_byte(line, 1), 1);
+        assert_eq!(utf16_to_byte(line, 2), 3);
+        assert_eq!(utf16_to_byte(line, 4), 7);
+        assert_eq!(utf16_to_byte(line, 5), 8);
+        // Inside the surrogate pair: the next character boundary.
+        assert_eq!(utf16_to_byte(line, 3), 7);
+    }
+
+    #[test]
+    fn utf16_to_byte_clamps_past_end_of_line() {
+        assert_eq!(utf16_to_byte("abc", 10), 3);
+        assert_eq!(utf16_to_byte("", 1), 0);
+    }
+
+    #[test]
+    fn byte_to_utf16_handles_multibyte_and_astral_chars() {
+        let line = "aé😀b";
+        assert_eq!(byte_to_utf16(line, 0), 0);
+        assert_eq!(byte_to_utf16(line, 1), 1);
+        assert_eq!(byte_to_utf16(line, 3), 2);
+        assert_eq!(byte_to_utf16(line, 7), 4);
+        assert_eq!(byte_to_utf16(line, 8), 5);
+        // Inside a character: the start of that character.
+        assert_eq!(byte_to_utf16(line, 5), 2);
+        // Past the end: the end of the line.
+        assert_eq!(byte_to_utf16(line, 100), 5);
+    }
+
+    #[test]
+    fn utf16_and_byte_offsets_round_trip() {
+        let line = "x😀yé z";
+        for (byte, _) in line.char_indices() {
+            assert_eq!(utf16_to_byte(line, byte_to_utf16(line, byte)), byte);
+        }
+    }
+
+    #[test]
+    fn position_to_char_clamps_to_line_and_document() {
+        let doc = Document::new("ab\r\ncd\n", 1);
+        assert_eq!(doc.position_to_char(pos(0, 1)), 1);
+        // Past the end of a CRLF line: before the line break.
+        assert_eq!(doc.position_to_char(pos(0, 10)), 2);
+        assert_eq!(doc.position_to_char(pos(1, 2)), 6);
+        // Past the last line: the end of the document.
+        assert_eq!(doc.position_to_char(pos(5, 0)), doc.rope.len_chars());
+    }
+
+    #[test]
+    fn char_to_position_counts_utf16_units() {
+        let doc = Document::new("😀a\nb", 1);
+        assert_eq!(doc.char_to_position(1), pos(0, 2));
+        assert_eq!(doc.char_to_position(3), pos(1, 0));
+        assert_eq!(doc.end_position(), pos(1, 1));
+        assert_eq!(doc.char_to_position(100), pos(1, 1));
+    }
+
+    #[test]
+    fn incremental_changes_edit_the_rope() {
+        let mut doc = Document::new("hello 😀 world\nsecond", 1);
+        // Replace the emoji (2 UTF-16 units) with text.
+        doc.apply_change(change(pos(0, 6), pos(0, 8), "there"));
+        assert_eq!(doc.text(), "hello there world\nsecond");
+        // Insert across the line break.
+        doc.apply_change(change(pos(0, 17), pos(1, 0), " "));
+        assert_eq!(doc.text(), "hello there world second");
+        // A range past the end appends.
+        doc.apply_change(change(pos(3, 0), pos(3, 0), "!"));
+        assert_eq!(doc.text(), "hello there world second!");
+    }
+
+    #[test]
+    fn incremental_changes_respect_crlf() {
+        let mut doc = Document::new("one\r\ntwo\r\n", 1);
+        doc.apply_change(change(pos(1, 0), pos(1, 3), "2"));
+        assert_eq!(doc.text(), "one\r\n2\r\n");
+        // An end past the line stops before "\r\n".
+        doc.apply_change(change(pos(0, 1), pos(0, 99), "NE"));
+        assert_eq!(doc.text(), "oNE\r\n2\r\n");
+    }
+
+    #[test]
+    fn line_ending_follows_the_document() {
+        assert_eq!(line_ending("one\r\ntwo"), "\r\n");
+        assert_eq!(line_ending("one\ntwo\n"), "\n");
+        assert_eq!(line_ending(""), "\n");
+    }
+
+    #[test]
+    fn unicode_line_separators_are_not_line_breaks() {
+        // LSP only breaks lines at "\n", "\r\n" and "\r".
+        let mut doc = Document::new("a\u{2028}b\nc", 1);
+        assert_eq!(doc.end_position(), pos(1, 1));
+        assert_eq!(doc.position_to_char(pos(0, 3)), 3);
+        doc.apply_change(change(pos(0, 2), pos(1, 0), "B "));
+        assert_eq!(doc.text(), "a\u{2028}B c");
+    }
+
+    #[test]
+    fn full_change_replaces_the_document() {
+        let mut doc = Document::new("old", 1);
+        doc.apply_change(TextDocumentContentChangeEvent {
+            range: None,
+            range_length: None,
+            text: "new".to_string(),
+        });
+        assert_eq!(doc.text(), "new");
+    }
+
+    #[test]
+    fn stale_versions_are_rejected() {
+        let uri = Url::parse("file:///vault/note.md").unwrap();
+        let mut store = DocumentStore::new();
+        store.open(uri.clone(), "a", 3);
+
+        let edit = || vec![change(pos(0, 1), pos(0, 1), "b")];
+        assert!(store.apply_changes(&uri, 3, edit()).is_err());
+        assert!(store.apply_changes(&uri, 2, edit()).is_err());
+        assert_eq!(store.text(&uri).as_deref(), Some("a"));
+
+        store.apply_changes(&uri, 4, edit()).unwrap();
+        assert_eq!(store.text(&uri).as_deref(), Some("ab"));
+        assert_eq!(store.version(&uri), Some(4));
+    }
+
+    #[test]
+    fn changes_to_unknown_documents_are_rejected() {
+        let uri = Url::parse("file:///vault/missing.md").unwrap();
+        assert!(
+            DocumentStore::new()
+                .apply_changes(&uri, 1, Vec::new())
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn line_index_maps_offsets_to_positions() {
+        let text = "é\n😀x";
+        let index = LineIndex::new(text);
+        assert_eq!(index.position(0), pos(0, 0));
+        assert_eq!(index.position(2), pos(0, 1));
+        assert_eq!(index.position(3), pos(1, 0));
+        assert_eq!(index.position(7), pos(1, 2));
+        assert_eq!(index.position(100), pos(1, 3));
+    }
+}
@@ -0,0 +1,43 @@
+// src/fuzzy.rs
+use fuse_rust::Fuse;
+
+/// The fuse-rust matcher used for every fuzzy search in the server.
+fn matcher() -> Fuse {
+    Fuse {
+        threshold: 0.3,
+        location: 0,
+        distance: 80,
+        max_pattern_length: 32,
+        is_case_sensitive: false,
+        tokenize: false,
+    }
+}
+
+/// Best score of `query` against any of `candidates` (lower is better), or `None`
+/// if none of them match.
+fn best_score<'a>(
+    fuse: &Fuse,
+    query: &str,
+    candidates: impl IntoIterator<Item = &'a str>,
+) -> Option<f64> {
+    candidates
+        .into_iter()
+        .filter_map(|candidate| fuse.search_text_in_string(query, candidate))
+        .map(|result| result.score)
+        .min_by(f64::total_cmp)
+}
+
+/// Keeps the items that match `query` on any of their keys, best match first.
+/// Items with equal scores keep their original order.
+pub fn rank<T, K>(query: &str, items: Vec<T>, keys: K) -> Vec<T>
+where
+    K: Fn(&T) -> Vec<&str>,
+{
+    let fuse = matcher();
+    let mut scored: Vec<(f64, T)> = items
+        .into_iter()
+        .filter_map(|item| Some((best_score(&fuse, query, keys(&item))?, item)))
+        .collect();
+    scored.sort_by(|a, b| a.0.total_cmp(&b.0));
+    scored.into_iter().map(|(_, item)| item).collect()
+}
@@ -0,0 +1,155 @@
+// src/handlers/code_actions.rs
+use serde_json::json;
+use std::path::Path;
+use tower_lsp::lsp_types::*;
+
+use crate::commands::{CommandRegistry, RUN_COMMAND};
+use crate::handlers::custom_commands::parse_command_syntax;
+use crate::handlers::workspace_commands::{APPEND, CREATE, REMOVE, Workspace};
+use crate::outline::code_block_lines;
+
+/// At most this many "add to workspace" actions are offered, so large vaults do
+/// not flood the menu.
+const MAX_ADD_ACTIONS: usize = 5;
+
+/// Workspace actions for the current note (`refactor`): add it to the workspaces
+/// it is not in, those holding notes of its folder first, remove it from those it
+/// is in, or create a workspace named after the note. A `%%` command line under
+/// the cursor is offered as an action running just that command (which also
+/// removes the line), and any command lines as one action running them all
+/// (`quickfix`). Only the kinds in `only` are returned, if it is given.
+pub fn code_actions(
+    uri: &Url,
+    text: &str,
+    range: Range,
+    only: Option<&[CodeActionKind]>,
+    workspaces: &[Workspace],
+    registry: &CommandRegistry,
+) -> Vec<CodeActionOrCommand> {
+    let mut actions = Vec::new();
+    if wants(only, &CodeActionKind::QUICKFIX) {
+        command_actions(&mut actions, uri, text, range, registry);
+    }
+    if wants(only, &CodeActionKind::REFACTOR) {
+        workspace_actions(&mut actions, uri, workspaces);
+    }
+    actions
+}
+
+/// Whether a request for the `only` kinds includes actions of `kind`. Kinds are
+/// hierarchical: `refactor` includes `refactor.rewrite`.
+pub fn wants(only: Option<&[CodeActionKind]>, kind: &CodeActionKind) -> bool {
+    only.is_none_or(|only| {
+        only.iter().any(|wanted| {
+            kind.as_str()
+                .strip_prefix(wanted.as_str())
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+        })
+    })
+}
+
+fn command_actions(
+    actions: &mut Vec<CodeActionOrCommand>,
+    uri: &Url,
+    text: &str,
+    range: Range,
+    registry: &CommandRegistry,
+) {
+    let in_code = code_block_lines(text);
+    // Lines holding a command of the registry, outside code blocks.
+    let command_lines: Vec<(u32, &str)> = text
+        .lines()
+        .enumerate()
+        .filter(|(i, line)| {
+            !in_code.get(*i).copied().unwrap_or(false)
+                && parse_command_syntax(line).is_some_and(|c| registry.get(c.name).is_some())
+        })
+        .map(|(i, line)| (i as u32, line))
+        .collect();
+
+    for &(line_no, line) in &command_lines {
+        if line_no < range.start.line || line_no > range.end.line {
+            continue;
+        }
+        actions.push(CodeActionOrCommand::CodeAction(CodeAction {
+            title: format!("Run `{}` now", line.trim()),
+            kind: Some(CodeActionKind::QUICKFIX),
+            command: Some(Command {
+                title: RUN_COMMAND.to_string(),
+                command: RUN_COMMAND.to_string(),
+                arguments: Some(vec![json!({ "uri": uri, "line": line_no })]),
+            }),
+            is_preferred: Some(true),
+            ..Default::default()
+        }));
+    }
+    if !command_lines.is_empty() {
+        actions.push(action(
+            "Run the `%%` commands in this note".to_string(),
+            CodeActionKind::QUICKFIX,
+            Command {
+                title: RUN_COMMAND.to_string(),
+                command: RUN_COMMAND.to_string(),
+                arguments: Some(vec![json!({ "uri": uri })]),
+            },
+        ));
+    }
+}
+
+fn workspace_actions(actions: &mut Vec<CodeActionOrCommand>, uri: &Url, workspaces: &[Workspace]) {
+    let Ok(note) = uri.to_file_path() else {
+        return;
+    };
+    let mut others: Vec<&Workspace> = workspaces.iter().filter(|w| !w.contains(&note)).collect();
+    // Stable, so workspaces keep their order within each group.
+    others.sort_by_key(|w| {
+        !w.notes
+            .iter()
+            .any(|n| Path::new(n).parent() == note.parent())
+    });
+    for workspace in others.into_iter().take(MAX_ADD_ACTIONS) {
+        actions.push(action(
+            format!("Add this note to workspace '{}'", workspace.name),
+            CodeActionKind::REFACTOR,
+            workspace_command(APPEND, &workspace.name, uri),
+        ));
+    }
+    for workspace in workspaces.iter().filter(|w| w.contains(&note)) {
+        actions.push(action(
+            format!("Remove this note from workspace '{}'", workspace.name),
+            CodeActionKind::REFACTOR,
+            workspace_command(REMOVE, &workspace.name, uri),
+        ));
+    }
+    // `%%` commands split their arguments on whitespace, so the name has none.
+    let name = note.file_stem().map(|stem| {
+        stem.to_string_lossy()
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join("-")
+    });
+    if let Some(name) = name.filter(|n| !n.is_empty() && !workspaces.iter().any(|w| &w.name == n)) {
+        actions.push(action(
+            format!("Create workspace '{}' with this note", name),
+            CodeActionKind::REFACTOR,
+            workspace_command(CREATE, &name, uri),
+        ));
+    }
+}
+
+fn action(title: String, kind: CodeActionKind, command: Command) -> CodeActionOrCommand {
+    CodeActionOrCommand::CodeAction(CodeAction {
+        title,
+        kind: Some(kind),
+        command: Some(command),
+        ..Default::default()
+    })
+}
+
+fn workspace_command(command: &str, workspace: &str, uri: &Url) -> Command {
+    Command {
+        title: command.to_string(),
+        command: command.to_string(),
+        arguments: Some(vec![json!({ "workspace": workspace, "uri": uri })]),
+    }
+}
@@ -1,118 +1,244 @@
-use notemancy_core::notes::utils::{get_title, list_all_notes};
-use serde::Deserialize;
-use serde_yaml;
-use std::env;
-use std::fs;
-use std::path::{Path, PathBuf};
+use crate::document_store::{byte_to_utf16, utf16_to_byte};
+use crate::fuzzy;
+use crate::vault_index::{NoteEntry, VaultIndex, VaultSet};
+use serde::{Deserialize, Serialize};
 use tower_lsp::jsonrpc::Result as LspResult;
 use tower_lsp::lsp_types::*;
 
-/// Configuration types corresponding to config.yaml.
-#[derive(Debug, Deserialize)]
-struct Vault {
-    name: String,
-    vault_directory: String,
-    publish_url: Option<String>,
-}
-
-#[derive(Debug, Deserialize)]
-struct ConfigFile {
-    vaults: Vec<Vault>,
-    default_vault: String,
-}
+/// Most notes returned in one response. When more notes match, the list is marked
+/// incomplete so the client asks again as the user keeps typing.
+const MAX_ITEMS: usize = 100;
 
-/// Reads the NOTEMANCY_CONF_DIR environment variable, loads config.yaml from that directory,
-/// and returns the vault_directory for the default vault.
-pub fn get_vault_directory() -> Result<PathBuf, String> {
-    let conf_dir = env::var("NOTEMANCY_CONF_DIR")
-        .map_err(|_| "Environment variable NOTEMANCY_CONF_DIR is not set".to_string())?;
-    let config_path = Path::new(&conf_dir).join("config.yaml");
-    let config_contents = fs::read_to_string(&config_path)
-        .map_err(|e| format!("Failed to read {}: {}", config_path.display(), e))?;
-    let config: ConfigFile = serde_yaml::from_str(&config_contents)
-        .map_err(|e| format!("Failed to parse {}: {}", config_path.display(), e))?;
-    // Find the default vault by name.
-    let default_vault = config.default_vault;
-    let vault = config
-        .vaults
-        .into_iter()
-        .find(|v| v.name == default_vault)
-        .ok_or_else(|| format!("Default vault '{}' not found in config", default_vault))?;
-    Ok(PathBuf::from(vault.vault_directory))
+/// Identifies the note behind a completion item so `completionItem/resolve` can
+/// attach its preview later.
+#[derive(Serialize, Deserialize)]
+struct NoteItemData {
+    vault: String,
+    path: String,
 }
 
 /// Provides wiki-link completions when the trigger is detected.
-/// It returns a completion item for each markdown note in the vault.
+/// Every note is offered under its title and under each of its aliases, ranked by
+/// fuzzy match of the typed text, and each item replaces everything between "[["
+/// and the closing "]]". Aliases insert the canonical path with the alias as title.
 pub fn provide_wiki_link_completions(
     params: CompletionParams,
     document_text: &str,
+    index: &VaultIndex,
 ) -> LspResult<Option<CompletionResponse>> {
     let pos = params.text_document_position.position;
     // Only offer completions if the current position is inside a wiki-link.
-    if !is_inside_wiki_link(document_text, pos) {
+    let Some(link) = wiki_link_context(document_text, pos) else {
         return Ok(None);
-    }
-
-    // Obtain the vault directory from the config.
-    let vault_dir = get_vault_directory().map_err(|e| tower_lsp::jsonrpc::Error {
-        code: tower_lsp::jsonrpc::ErrorCode::InternalError,
-        message: e,
-        data: None,
-    })?;
+    };
 
-    // List all markdown note paths (relative paths) in the vault.
-    let note_paths = list_all_notes(&vault_dir, true).map_err(|err| tower_lsp::jsonrpc::Error {
-        code: tower_lsp::jsonrpc::ErrorCode::InternalError,
-        message: err.to_string(),
-        data: None,
-    })?;
+    // After a '#', complete headings and block IDs of the linked note instead.
+    if let Some((path, anchor)) = link.prefix.split_once('#') {
+        let range = Range {
+            start: Position {
+                line: pos.line,
+                character: pos.character - anchor.encode_utf16().count() as u32,
+            },
+            end: pos,
+        };
+        let items = anchor_completions(path.trim(), range, document_text, index);
+        return Ok(Some(CompletionResponse::Array(items)));
+    }
 
-    let mut items = Vec::new();
+    let query = link.prefix.trim();
+    // (note, display text, whether the display text is an alias)
+    let candidates: Vec<(&NoteEntry, &str, bool)> = index
+        .notes()
+        .flat_map(|note| {
+            std::iter::once((note, note.title.as_str(), false))
+                .chain(note.aliases.iter().map(move |a| (note, a.as_str(), true)))
+        })
+        .collect();
+    let candidates = if query.is_empty() {
+        candidates
+    } else {
+        fuzzy::rank(query, candidates, |(note, display, is_alias)| {
+            if *is_alias {
+                vec![*display]
+            } else {
+                vec![*display, note.path.as_str()]
+            }
+        })
+    };
 
-    // For each note, use get_title to extract its title, and build a completion item.
-    for note in note_paths {
-        let full_path = vault_dir.join(&note);
-        let title = match get_title(&full_path) {
-            Ok(t) => t,
-            Err(_) => continue, // Skip note if its title cannot be determined.
-        };
-        let insert_text = format!("{} | {}", note, title);
-        let item = CompletionItem {
-            label: title.clone(),
+    let is_incomplete = candidates.len() > MAX_ITEMS;
+    let items = candidates
+        .into_iter()
+        .take(MAX_ITEMS)
+        .enumerate()
+        .map(|(rank, (note, display, is_alias))| CompletionItem {
+            label: display.to_string(),
             kind: Some(CompletionItemKind::FILE),
+            detail: Some(if is_alias {
+                format!("{} (alias of {})", note.path, note.title)
+            } else {
+                note.path.clone()
+            }),
+            // The notes are already filtered and ranked here, so stop the client from
+            // filtering them again against the typed text.
+            filter_text: Some(link.prefix.to_string()),
+            sort_text: Some(format!("{:04}", rank)),
             // Insert both the relative path and the title.
-            insert_text: Some(insert_text),
-            detail: Some(note),
+            text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                range: link.range,
+                new_text: format!("{} | {}]]", note.path, display),
+            })),
+            data: serde_json::to_value(NoteItemData {
+                vault: index.name().to_string(),
+                path: note.path.clone(),
+            })
+            .ok(),
             ..Default::default()
-        };
-        items.push(item);
+        })
+        .collect();
+
+    Ok(Some(CompletionResponse::List(CompletionList {
+        is_incomplete,
+        items,
+    })))
+}
+
+/// Attaches a preview of the note to a wiki-link completion item: its summary,
+/// first paragraph, tags and modified date. Other items are returned unchanged.
+pub fn resolve_completion_item(mut item: CompletionItem, vaults: &VaultSet) -> CompletionItem {
+    let Some(data) = item
+        .data
+        .clone()
+        .and_then(|d| serde_json::from_value::<NoteItemData>(d).ok())
+    else {
+        return item;
+    };
+    let Some(index) = vaults.iter().find(|v| v.name() == data.vault) else {
+        return item;
+    };
+    let Some(note) = index.get(&data.path) else {
+        return item;
+    };
+
+    let mut sections = vec![format!("**{}**", note.title)];
+    if let Some(summary) = note.summary() {
+        sections.push(format!("> {}", summary));
+    }
+    if let Some(paragraph) = note.first_paragraph() {
+        sections.push(paragraph);
+    }
+    let mut meta = Vec::new();
+    if !note.tags.is_empty() {
+        let tags: Vec<String> = note.tags.iter().map(|t| format!("#{}", t)).collect();
+        meta.push(format!("Tags: {}", tags.join(" ")));
+    }
+    let modified = std::fs::metadata(index.root().join(&note.path)).and_then(|m| m.modified());
+    if let Ok(modified) = modified {
+        let date = humantime::format_rfc3339_seconds(modified).to_string();
+        meta.push(format!("Modified: {}", &date[..10]));
+    }
+    if !meta.is_empty() {
+        sections.push(meta.join("  \n"));
     }
 
-    Ok(Some(CompletionResponse::Array(items)))
+    item.documentation = Some(Documentation::MarkupContent(MarkupContent {
+        kind: MarkupKind::Markdown,
+        value: sections.join("\n\n"),
+    }));
+    item
 }
 
-/// Returns true if the cursor is considered to be “inside” a wiki-link.
+/// Completion items for the headings and `^block-id`s of the note at `path`.
+/// An empty path refers to the current document.
+fn anchor_completions(
+    path: &str,
+    range: Range,
+    document_text: &str,
+    index: &VaultIndex,
+) -> Vec<CompletionItem> {
+    let current;
+    let note = if path.is_empty() {
+        current = NoteEntry::parse("", document_text);
+        &current
+    } else {
+        match index.resolve(path) {
+            Some(note) => note,
+            None => return Vec::new(),
+        }
+    };
+
+    let headings = note.headings.iter().map(|heading| CompletionItem {
+        label: heading.text.clone(),
+        kind: Some(CompletionItemKind::REFERENCE),
+        detail: Some(format!("{} {}", "#".repeat(heading.level), heading.text)),
+        text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+            range,
+            new_text: heading.text.clone(),
+        })),
+        ..Default::default()
+    });
+    let lines: Vec<&str> = note.content.lines().collect();
+    let blocks = note.block_ids.iter().map(|block| CompletionItem {
+        label: format!("^{}", block.id),
+        kind: Some(CompletionItemKind::REFERENCE),
+        detail: lines.get(block.line as usize).map(|l| l.trim().to_string()),
+        text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+            range,
+            new_text: format!("^{}", block.id),
+        })),
+        ..Default::default()
+    });
+    headings.chain(blocks).collect()
+}
+
+/// The wiki-link being typed at the cursor.
+struct LinkContext<'a> {
+    /// Text between the opening "[[" and the cursor.
+    prefix: &'a str,
+    /// From just after "[[" to the end of the closing "]]", or to the cursor if the
+    /// link is not closed yet.
+    range: Range,
+}
+
+/// If the cursor is considered to be “inside” a wiki-link, returns the text between
+/// the opening "[[" and the cursor along with the range a completion should replace.
 /// This function looks at the current line, finds the last occurrence of "[[" before the cursor,
 /// and if a closing "]]" exists it ensures the cursor is positioned before it.
-fn is_inside_wiki_link(text: &str, position: Position) -> bool {
+fn wiki_link_context(text: &str, position: Position) -> Option<LinkContext<'_>> {
     let lines: Vec<&str> = text.lines().collect();
     if (position.line as usize) >= lines.len() {
-        return false;
+        return None;
     }
     let line = lines[position.line as usize];
-    // Consider only the text before the cursor.
-    let prefix = &line[..(position.character as usize).min(line.len())];
-    if let Some(start_index) = prefix.rfind("[[") {
-        // Look for a closing "]]" after the opening delimiter.
-        let after_open = &line[start_index..];
-        if let Some(close_offset) = after_open.find("]]") {
-            let close_index = start_index + close_offset;
-            // If the cursor is before the closing delimiter, we are inside.
-            return (position.character as usize) <= close_index;
-        } else {
-            // No closing delimiter means it's a partial wiki-link.
-            return true;
+    // Consider only the text before the cursor (the LSP column is in UTF-16 code units).
+    let cursor = utf16_to_byte(line, position.character);
+    let prefix = &line[..cursor];
+    let start_index = prefix.rfind("[[")?;
+    let inside = &prefix[start_index + 2..];
+    // Look for a closing "]]" after the opening delimiter.
+    let after_open = &line[start_index..];
+    let end = if let Some(close_offset) = after_open.find("]]") {
+        let close_index = start_index + close_offset;
+        // If the cursor is past the closing delimiter, we are not inside.
+        if cursor > close_index {
+            return None;
         }
-    }
-    false
+        close_index + 2
+    } else {
+        // No closing delimiter means it's a partial wiki-link.
+        cursor
+    };
+    Some(LinkContext {
+        prefix: inside,
+        range: Range {
+            start: Position {
+                line: position.line,
+                character: byte_to_utf16(line, start_index + 2),
+            },
+            end: Position {
+                line: position.line,
+                character: byte_to_utf16(line, end),
+            },
+        },
+    })
 }
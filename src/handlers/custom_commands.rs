@@ -1,90 +1,269 @@
 // src/handlers/custom_commands.rs
 
 use regex::Regex;
-use std::path::{Path, PathBuf};
-use tower_lsp::lsp_types::Url;
-
-// Use the vault lookup helper to get the vault directory.
-use crate::handlers::completion::get_vault_directory;
-
-// Import the actual CRUD functions from notemancy-core.
-use notemancy_core::workspaces::crud;
-
-/// Processes custom workspace commands in the markdown text.
-/// This function looks for lines beginning with "%%" that specify a command,
-/// executes the appropriate notemancy-core workspace function, and then returns
-/// the text with those command lines removed.
-///
-/// Supported commands:
-/// - %%nw workspace_name  => Create a new workspace and add the current note.
-/// - %%atw workspace_name => Append the current note to the workspace.
-/// - %%dfw workspace_name => Remove the current note from the workspace.
-pub fn process_custom_commands(text: &str, file_uri: &Url) -> Result<String, String> {
-    // Obtain the vault directory from the configuration.
-    let vault_dir = get_vault_directory().map_err(|e| e)?;
-
-    // Derive the current file path from the document URI.
-    let file_path_buf: PathBuf = file_uri
-        .to_file_path()
-        .map_err(|_| "Invalid file URI".to_string())?;
-    let file_path_str = file_path_buf
-        .to_str()
-        .ok_or("Failed to convert file path to string")?;
-
-    // Regex to match commands like: %%nw workspace_name
-    let command_re = Regex::new(r"^%%(nw|atw|dfw)\s+(\S+)$").map_err(|e| e.to_string())?;
-
-    let mut cleaned_lines = Vec::new();
-
-    for line in text.lines() {
-        let trimmed = line.trim();
-        if let Some(caps) = command_re.captures(trimmed) {
-            let command = caps.get(1).unwrap().as_str();
-            let workspace_name = caps.get(2).unwrap().as_str();
-
-            match command {
-                "nw" => {
-                    // Create a new workspace with the current note.
-                    if let Err(e) =
-                        crud::create_workspace(&vault_dir, workspace_name, file_path_str)
-                    {
-                        eprintln!(
-                            "Error creating workspace '{}' with note '{}': {}",
-                            workspace_name, file_path_str, e
-                        );
-                    }
-                }
-                "atw" => {
-                    // Append the current note to the workspace.
-                    if let Err(e) =
-                        crud::append_to_workspace(&vault_dir, workspace_name, file_path_str)
-                    {
-                        eprintln!(
-                            "Error appending note '{}' to workspace '{}': {}",
-                            file_path_str, workspace_name, e
-                        );
-                    }
-                }
-                "dfw" => {
-                    // Remove the current note from the workspace.
-                    if let Err(e) =
-                        crud::remove_from_workspace(&vault_dir, workspace_name, file_path_str)
-                    {
-                        eprintln!(
-                            "Error removing note '{}' from workspace '{}': {}",
-                            file_path_str, workspace_name, e
-                        );
-                    }
-                }
-                _ => {
-                    eprintln!("Unknown command: {}", command);
-                }
+use std::ops::Range as ByteRange;
+use std::sync::LazyLock;
+use tower_lsp::lsp_types::*;
+
+use crate::commands::{CommandContext, CommandRegistry, Mutation, check_arity};
+use crate::document_store::{byte_to_utf16, line_ending, utf16_to_byte};
+use crate::outline::code_block_lines;
+
+/// Regex to match command lines like: %%nw workspace_name
+/// `%%` followed by a space is a comment, not a command.
+static COMMAND_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^\s*%%(\w*)").unwrap());
+
+/// A `%%` line as written, before validation. Spans are byte ranges in the line.
+#[derive(Debug, Clone)]
+pub struct CommandLine<'a> {
+    pub name: &'a str,
+    /// The name including the leading `%%`.
+    pub name_span: ByteRange<usize>,
+    pub args: Vec<(&'a str, ByteRange<usize>)>,
+}
+
+/// Splits a line starting with `%%name` into the command name and its arguments.
+/// Inline comments (`%%text%%`) are not commands.
+pub fn parse_command_syntax(line: &str) -> Option<CommandLine<'_>> {
+    let trimmed = line.trim();
+    if trimmed.len() > 2 && trimmed.ends_with("%%") {
+        return None;
+    }
+    let caps = COMMAND_RE.captures(line)?;
+    let name = caps.get(1)?;
+    let mut args = Vec::new();
+    let mut offset = name.end();
+    for arg in line[name.end()..].split_whitespace() {
+        let start = offset + line[offset..].find(arg)?;
+        offset = start + arg.len();
+        args.push((arg, start..offset));
+    }
+    Some(CommandLine {
+        name: name.as_str(),
+        name_span: name.start() - 2..name.end(),
+        args,
+    })
+}
+
+/// Completes command names after `%%` and command arguments.
+pub fn command_completions(
+    text: &str,
+    position: Position,
+    registry: &CommandRegistry,
+    ctx: &CommandContext,
+) -> Option<CompletionResponse> {
+    let line = text.lines().nth(position.line as usize)?;
+    let cursor = utf16_to_byte(line, position.character);
+    let command = parse_command_syntax(&line[..cursor])?;
+    let range_from = |start: usize| Range {
+        start: Position {
+            line: position.line,
+            character: byte_to_utf16(line, start),
+        },
+        end: position,
+    };
+
+    if command.args.is_empty() && command.name_span.end == cursor {
+        // Still typing the name.
+        let range = range_from(command.name_span.start + 2);
+        let items = registry
+            .iter()
+            .map(|c| CompletionItem {
+                label: format!("%%{}", c.name()),
+                kind: Some(CompletionItemKind::KEYWORD),
+                detail: Some(c.description().to_string()),
+                filter_text: Some(c.name().to_string()),
+                text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                    range,
+                    new_text: format!("{} ", c.name()),
+                })),
+                ..Default::default()
+            })
+            .collect();
+        return Some(CompletionResponse::Array(items));
+    }
+
+    let custom = registry.get(command.name)?;
+    // The argument being typed, or a new one after whitespace.
+    let (index, start) = match command.args.last() {
+        Some((_, span)) if span.end == cursor => (command.args.len() - 1, span.start),
+        _ => (command.args.len(), cursor),
+    };
+    if !custom.arity().contains(&(index + 1)) {
+        return None;
+    }
+    let range = range_from(start);
+    let items = custom
+        .complete(index, ctx)
+        .into_iter()
+        .map(|candidate| CompletionItem {
+            label: candidate.clone(),
+            kind: Some(CompletionItemKind::VALUE),
+            text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                range,
+                new_text: candidate,
+            })),
+            ..Default::default()
+        })
+        .collect();
+    Some(CompletionResponse::Array(items))
+}
+
+/// Explains the command under the cursor.
+pub fn command_hover(text: &str, position: Position, registry: &CommandRegistry) -> Option<Hover> {
+    let line = text.lines().nth(position.line as usize)?;
+    let command = parse_command_syntax(line)?;
+    let cursor = utf16_to_byte(line, position.character);
+    if cursor < command.name_span.start || cursor > command.name_span.end {
+        return None;
+    }
+    let custom = registry.get(command.name)?;
+    let signature = match custom.usage() {
+        "" => format!("**%%{}**", custom.name()),
+        usage => format!("**%%{}** `{}`", custom.name(), usage),
+    };
+    let value = format!(
+        "{}\n\n{}\n\nRuns with `notemancy.runCommands`, or on formatting with `runCommands` on, and the line is removed.",
+        signature,
+        custom.description()
+    );
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value,
+        }),
+        range: Some(Range {
+            start: Position {
+                line: position.line,
+                character: byte_to_utf16(line, command.name_span.start),
+            },
+            end: Position {
+                line: position.line,
+                character: byte_to_utf16(line, command.name_span.end),
+            },
+        }),
+    })
+}
+
+/// The outcome of processing the `%%` lines of a document.
+#[derive(Debug, Default)]
+pub struct ProcessedCommands {
+    /// The document with the command lines removed and document edits applied.
+    pub text: String,
+    /// Everything the commands do, in order, including the document edits.
+    pub mutations: Vec<Mutation>,
+    /// Commands that could not be planned; their lines are kept.
+    pub errors: Vec<String>,
+}
+
+/// Processes custom commands in the markdown text: each line starting with `%%`
+/// and a known command is planned, then removed from the text. Document edits
+/// (tags, frontmatter, templates) are applied to the returned text; vault
+/// mutations are only returned, for the caller to apply or preview. With `only_line`,
+/// the command on that line is the only one processed.
+pub fn process_custom_commands(
+    text: &str,
+    registry: &CommandRegistry,
+    ctx: &CommandContext,
+    only_line: Option<usize>,
+) -> ProcessedCommands {
+    let mut processed = ProcessedCommands::default();
+    let mut lines: Vec<String> = Vec::new();
+    let in_code = code_block_lines(text);
+
+    for (i, line) in text.lines().enumerate() {
+        let command = parse_command_syntax(line).filter(|_| {
+            !in_code.get(i).copied().unwrap_or(false) && only_line.is_none_or(|only| only == i)
+        });
+        let Some(custom) = command.as_ref().and_then(|c| registry.get(c.name)) else {
+            lines.push(line.to_string());
+            continue;
+        };
+        let args: Vec<&str> = command
+            .iter()
+            .flat_map(|c| &c.args)
+            .map(|(a, _)| *a)
+            .collect();
+        let planned = check_arity(custom, args.len())
+            .and_then(|_| custom.plan(&args, ctx))
+            .and_then(|mutations| {
+                let replacement = mutations
+                    .iter()
+                    .map(Mutation::replacement)
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok((mutations, replacement))
+            });
+        match planned {
+            Ok((mutations, replacement)) => {
+                // The command line is replaced by the inserted templates, if any.
+                lines.extend(replacement.into_iter().flatten());
+                processed.mutations.extend(mutations);
+            }
+            Err(e) => {
+                processed.errors.push(format!("{}: {}", line.trim(), e));
+                lines.push(line.to_string());
             }
-            // Skip this command line from the output.
-        } else {
-            cleaned_lines.push(line);
         }
     }
 
-    Ok(cleaned_lines.join("\n"))
+    // Keep the document's line endings, including the last one.
+    let newline = line_ending(text);
+    let mut result = lines.join(newline);
+    if text.ends_with('\n') {
+        result.push_str(newline);
+    }
+    for mutation in &processed.mutations {
+        result = mutation.apply_to_text(&result);
+    }
+    processed.text = result;
+    processed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::Path;
+
+    fn run(text: &str, vault_dir: &Path) -> String {
+        let note = vault_dir.join("note.md");
+        let ctx = CommandContext {
+            vault_dir,
+            note: &note,
+            workspaces: None,
+        };
+        let processed = process_custom_commands(text, &CommandRegistry::builtin(), &ctx, None);
+        assert!(processed.errors.is_empty(), "{:?}", processed.errors);
+        processed.text
+    }
+
+    #[test]
+    fn commands_keep_the_notes_line_endings() {
+        let vault = Path::new("/vault");
+        assert_eq!(
+            run("%%tag idea\nbody\n", vault),
+            "---\ntags: [idea]\n---\nbody\n"
+        );
+        assert_eq!(
+            run("%%tag idea\r\nbody\r\n", vault),
+            "---\r\ntags: [idea]\r\n---\r\nbody\r\n"
+        );
+        assert_eq!(
+            run("body\n%%tag idea", vault),
+            "---\ntags: [idea]\n---\nbody"
+        );
+    }
+
+    #[test]
+    fn templates_take_the_notes_line_endings() {
+        let vault = std::env::temp_dir().join(format!("ncylsp-templates-{}", std::process::id()));
+        fs::create_dir_all(vault.join("templates")).unwrap();
+        fs::write(vault.join("templates/crlf.md"), "a\r\nb\r\n").unwrap();
+        fs::write(vault.join("templates/lf.md"), "a\nb\n\n").unwrap();
+        let lf = run("x\n%%template crlf\ny\n", &vault);
+        let crlf = run("x\r\n%%template lf\r\ny\r\n", &vault);
+        fs::remove_dir_all(&vault).unwrap();
+        assert_eq!(lf, "x\na\nb\ny\n");
+        assert_eq!(crlf, "x\r\na\r\nb\r\ny\r\n");
+    }
 }
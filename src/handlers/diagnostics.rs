@@ -0,0 +1,463 @@
+// src/handlers/diagnostics.rs
+use tower_lsp::lsp_types::*;
+
+use crate::commands::{CommandContext, CommandRegistry, check_arity};
+use crate::config::{ConfigFile, ConfigKey, FrontmatterSchema};
+use crate::document_store::line_range;
+use crate::handlers::custom_commands::parse_command_syntax;
+use crate::handlers::frontmatter::{frontmatter_lines, line_key};
+use crate::outline::{code_block_lines, code_spans};
+use crate::settings::{DiagnosticsSettings, Severity};
+use crate::vault_index::{NoteEntry, VaultIndex};
+use crate::wikilink::{find_wikilinks, split_anchor};
+
+/// Checks every wiki-link in the document and reports links to notes that are
+/// not in the vault, links to missing headings or blocks, and malformed links.
+/// Each check uses the severity from the settings and can be turned off there.
+pub fn wikilink_diagnostics(
+    text: &str,
+    index: &VaultIndex,
+    settings: &DiagnosticsSettings,
+) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    // Parsed view of the document itself, used for `[[#Heading]]` links.
+    let current = NoteEntry::parse("", text);
+    let in_code = code_block_lines(text);
+    // Links written inside `inline code` are not links either.
+    let code_spans = code_spans(text);
+    let in_code_span = |offset: usize| code_spans.iter().any(|span| span.contains(&offset));
+    let lines = text.split_inclusive('\n').scan(0, |offset, line| {
+        let start = *offset;
+        *offset += line.len();
+        Some((start, line.trim_end_matches(['\n', '\r'])))
+    });
+
+    for (i, (offset, line)) in lines.enumerate() {
+        if in_code.get(i).copied().unwrap_or(false) {
+            continue;
+        }
+        let line_no = i as u32;
+        let links: Vec<_> = find_wikilinks(line)
+            .into_iter()
+            .filter(|link| !in_code_span(offset + link.start))
+            .collect();
+
+        for link in &links {
+            let range = line_range(line, line_no, link.start..link.end);
+            let (path, anchor) = split_anchor(link.path);
+
+            if path.is_empty() && anchor.is_none_or(str::is_empty) {
+                diagnostics.extend(diagnostic(
+                    range,
+                    settings.malformed_link,
+                    "Wiki-link has no target path".to_string(),
+                ));
+                continue;
+            }
+
+            let target = if path.is_empty() {
+                Some(&current)
+            } else {
+                index.resolve(path)
+            };
+            let Some(target) = target else {
+                diagnostics.extend(diagnostic(
+                    range,
+                    settings.broken_link,
+                    format!("Note '{}' does not exist in the vault", path),
+                ));
+                continue;
+            };
+
+            if let Some(anchor) = anchor.filter(|a| !a.is_empty()) {
+                if target.anchor_line(anchor).is_none() {
+                    let message = match anchor.strip_prefix('^') {
+                        Some(block_id) => {
+                            format!("Block '^{}' not found in '{}'", block_id, target.path)
+                        }
+                        None => format!("Heading '{}' not found in '{}'", anchor, target.path),
+                    };
+                    diagnostics.extend(diagnostic(range, settings.missing_anchor, message));
+                }
+            }
+        }
+
+        // Any "[[" that is not the start of a well-formed link is malformed.
+        for (start, _) in line.match_indices("[[") {
+            let covered = links.iter().any(|l| start >= l.start && start < l.end);
+            if !covered && !in_code_span(offset + start) {
+                let end = line[start..]
+                    .find("]]")
+                    .map(|offset| start + offset + 2)
+                    .unwrap_or(line.len());
+                diagnostics.extend(diagnostic(
+                    line_range(line, line_no, start..end),
+                    settings.malformed_link,
+                    "Malformed wiki-link".to_string(),
+                ));
+            }
+        }
+    }
+
+    diagnostics
+}
+
+/// Checks the frontmatter block: that it is valid YAML and, against the vault's
+/// schema, that required keys are present and values have the expected type and
+/// one of the allowed values.
+pub fn frontmatter_diagnostics(
+    text: &str,
+    schema: &FrontmatterSchema,
+    settings: &DiagnosticsSettings,
+) -> Vec<Diagnostic> {
+    let Some(block) = frontmatter_lines(text) else {
+        return Vec::new();
+    };
+    let lines: Vec<&str> = text.lines().collect();
+    let yaml = lines[block.clone()].join("\n");
+    let frontmatter: serde_yaml::Value = match serde_yaml::from_str(&yaml) {
+        Ok(value) => value,
+        Err(e) => {
+            let line_no = block.start + e.location().map(|l| l.line() - 1).unwrap_or(0);
+            let line = lines.get(line_no).copied().unwrap_or("");
+            return diagnostic(
+                line_range(line, line_no as u32, 0..line.len()),
+                settings.invalid_frontmatter,
+                format!("Invalid frontmatter: {}", e),
+            )
+            .into_iter()
+            .collect();
+        }
+    };
+
+    let mut diagnostics = Vec::new();
+    for (key, field) in schema {
+        let value = frontmatter.get(key.as_str()).filter(|v| !v.is_null());
+        let Some(value) = value else {
+            if field.required {
+                diagnostics.extend(diagnostic(
+                    line_range(lines[0], 0, 0..lines[0].len()),
+                    settings.frontmatter_schema,
+                    format!("Missing required frontmatter key '{}'", key),
+                ));
+            }
+            continue;
+        };
+        let line_no = block
+            .clone()
+            .find(|&i| line_key(lines[i]) == Some(key.as_str()))
+            .unwrap_or(0);
+        let range = line_range(lines[line_no], line_no as u32, 0..lines[line_no].len());
+
+        if !field.kind.matches(value) {
+            diagnostics.extend(diagnostic(
+                range,
+                settings.frontmatter_schema,
+                format!("'{}' should be a {}", key, field.kind.name()),
+            ));
+            continue;
+        }
+        if field.values.is_empty() {
+            continue;
+        }
+        let values: Vec<&serde_yaml::Value> = match value {
+            serde_yaml::Value::Sequence(items) => items.iter().collect(),
+            other => vec![other],
+        };
+        for value in values.into_iter().filter_map(|v| v.as_str()) {
+            if !field.values.iter().any(|allowed| allowed == value) {
+                diagnostics.extend(diagnostic(
+                    range,
+                    settings.frontmatter_schema,
+                    format!(
+                        "'{}' is not an allowed value for '{}' (expected one of: {})",
+                        value,
+                        key,
+                        field.values.join(", ")
+                    ),
+                ));
+            }
+        }
+    }
+    diagnostics
+}
+
+/// Checks `%%` command lines: unknown commands and the wrong number of
+/// arguments. With a context, each command also checks its arguments against the
+/// vault, e.g. that a workspace or template exists.
+pub fn command_diagnostics(
+    text: &str,
+    registry: &CommandRegistry,
+    ctx: Option<&CommandContext>,
+    settings: &DiagnosticsSettings,
+) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let in_code = code_block_lines(text);
+
+    for (i, line) in text.lines().enumerate() {
+        if in_code.get(i).copied().unwrap_or(false) {
+            continue;
+        }
+        let Some(command) = parse_command_syntax(line) else {
+            continue;
+        };
+        // A bare `%%` or `%% text` is a comment.
+        if command.name.is_empty() {
+            continue;
+        }
+        let line_no = i as u32;
+        let name_range = line_range(line, line_no, command.name_span.clone());
+
+        let Some(custom) = registry.get(command.name) else {
+            let known: Vec<String> = registry.iter().map(|c| format!("%%{}", c.name())).collect();
+            diagnostics.extend(diagnostic(
+                name_range,
+                settings.invalid_command,
+                format!(
+                    "Unknown command '%%{}' (expected one of: {})",
+                    command.name,
+                    known.join(", ")
+                ),
+            ));
+            continue;
+        };
+        if let Err(message) = check_arity(custom, command.args.len()) {
+            let range = match command.args.get(*custom.arity().end()..) {
+                Some([first, .., last]) => line_range(line, line_no, first.1.start..last.1.end),
+                Some([only]) => line_range(line, line_no, only.1.clone()),
+                _ => name_range,
+            };
+            diagnostics.extend(diagnostic(range, settings.invalid_command, message));
+            continue;
+        }
+
+        let Some(ctx) = ctx else {
+            continue;
+        };
+        let args: Vec<&str> = command.args.iter().map(|(arg, _)| *arg).collect();
+        for (index, message) in custom.validate(&args, ctx) {
+            let span = &command.args[index].1;
+            diagnostics.extend(diagnostic(
+                line_range(line, line_no, span.clone()),
+                settings.command_argument,
+                message,
+            ));
+        }
+    }
+
+    diagnostics
+}
+
+/// Diagnostics for config.yaml: the parse error if it does not parse, otherwise
+/// each validation problem, placed on the first line mentioning the offending value.
+pub fn config_diagnostics(
+    text: &str,
+    parsed: &Result<ConfigFile, serde_yaml::Error>,
+) -> Vec<Diagnostic> {
+    let config = match parsed {
+        Ok(config) => config,
+        Err(e) => {
+            let (line_no, start) = e
+                .location()
+                .map(|l| (l.line().saturating_sub(1), l.column().saturating_sub(1)))
+                .unwrap_or((0, 0));
+            let line = text.lines().nth(line_no).unwrap_or("");
+            let start = line
+                .char_indices()
+                .nth(start)
+                .map(|(i, _)| i)
+                .unwrap_or(line.len());
+            return diagnostic(
+                line_range(line, line_no as u32, start..line.len()),
+                Severity::Error,
+                format!("Invalid config: {}", e),
+            )
+            .into_iter()
+            .collect();
+        }
+    };
+
+    config
+        .validate()
+        .into_iter()
+        .filter_map(|problem| {
+            let (line_no, start, end) = locate_config_value(text, &problem.path, problem.on_key);
+            let line = text.lines().nth(line_no).unwrap_or("");
+            diagnostic(
+                line_range(line, line_no as u32, start..end),
+                Severity::Error,
+                problem.message,
+            )
+        })
+        .collect()
+}
+
+/// Finds the value at `path` in block-style YAML, or its key when `on_key` is set
+/// or the value does not start on the key's line. Returns the line and byte range
+/// of the deepest step found, so a path into a flow-style mapping points at the
+/// key holding it.
+fn locate_config_value(text: &str, path: &[ConfigKey], on_key: bool) -> (usize, usize, usize) {
+    let lines: Vec<&str> = text.lines().collect();
+    // The lines of the current node, with the column where their content starts.
+    let mut node: Vec<(usize, usize)> = lines
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| {
+            let content = line.trim_start();
+            !content.is_empty() && !content.starts_with('#')
+        })
+        .map(|(i, line)| (i, line.len() - line.trim_start().len()))
+        .collect();
+    let mut found = (0, 0, 0);
+
+    for (step, key) in path.iter().enumerate() {
+        // Entries of a node all start at its first column; deeper lines are their values.
+        let Some(&(_, column)) = node.first() else {
+            break;
+        };
+        let mut entries = node
+            .iter()
+            .enumerate()
+            .filter(|(_, (line, col))| *col == column && lines[*line].len() > *col);
+        let hit = match key {
+            ConfigKey::Index(n) => entries
+                .filter(|(_, (line, col))| lines[*line][*col..].starts_with('-'))
+                .nth(*n),
+            ConfigKey::Field(name) => entries.find(|(_, (line, col))| {
+                lines[*line][*col..]
+                    .split_once(':')
+                    .is_some_and(|(k, _)| k.trim().trim_matches(['"', '\'']) == name)
+            }),
+        };
+        let Some((pos, &(line_no, col))) = hit else {
+            break;
+        };
+        let line = lines[line_no];
+        let children = node[pos + 1..]
+            .iter()
+            .take_while(|(_, c)| *c > column)
+            .copied();
+
+        match key {
+            ConfigKey::Index(_) => {
+                found = (line_no, col, col + 1);
+                // The item's first entry may follow the dash on the same line.
+                let after_dash = &line[col + 1..];
+                let first = (!after_dash.trim().is_empty())
+                    .then(|| (line_no, line.len() - after_dash.trim_start().len()));
+                node = first.into_iter().chain(children).collect();
+            }
+            ConfigKey::Field(_) => {
+                let key_end = col + line[col..].find(':').unwrap_or(0);
+                let after = &line[key_end + 1..];
+                let value = after.split(" #").next().unwrap_or("").trim();
+                let value_start = key_end + 1 + (after.len() - after.trim_start().len());
+                found = if on_key || step + 1 < path.len() || value.is_empty() {
+                    (line_no, col, key_end)
+                } else {
+                    (line_no, value_start, value_start + value.len())
+                };
+                node = children.collect();
+            }
+        }
+    }
+    found
+}
+
+/// Builds a diagnostic, or nothing if the check's severity is `off`.
+fn diagnostic(range: Range, severity: Severity, message: String) -> Option<Diagnostic> {
+    Some(Diagnostic {
+        range,
+        severity: Some(severity.to_lsp()?),
+        source: Some("notemancy".to_string()),
+        message,
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field(name: &str) -> ConfigKey {
+        ConfigKey::Field(name.to_string())
+    }
+
+    /// The line found for `path` and the text of the range on it.
+    fn locate<'a>(text: &'a str, path: &[ConfigKey], on_key: bool) -> (usize, &'a str) {
+        let (line, start, end) = locate_config_value(text, path, on_key);
+        (line, &text.lines().nth(line).unwrap_or("")[start..end])
+    }
+
+    #[test]
+    fn links_in_inline_code_are_not_checked() {
+        let text = "`[[nope]]` and ``[[x`` and [[gone]]\n";
+        let diagnostics = wikilink_diagnostics(
+            text,
+            &VaultIndex::default(),
+            &DiagnosticsSettings::default(),
+        );
+        let starts: Vec<u32> = diagnostics
+            .iter()
+            .map(|d| d.range.start.character)
+            .collect();
+        assert_eq!(starts, [27]);
+    }
+
+    #[test]
+    fn locates_values_in_block_sequences() {
+        let text = "default_vault: main\n\
+                    vaults:\n  \
+                    - name: main\n    \
+                    vault_directory: /a\n  \
+                    - name: work\n    \
+                    vault_directory: /b  # comment\n";
+        let path = [
+            field("vaults"),
+            ConfigKey::Index(1),
+            field("vault_directory"),
+        ];
+        assert_eq!(locate(text, &path, false), (5, "/b"));
+        assert_eq!(locate(text, &path, true), (5, "vault_directory"));
+        // The first entry of an item follows its dash.
+        let path = [field("vaults"), ConfigKey::Index(1), field("name")];
+        assert_eq!(locate(text, &path, false), (4, "work"));
+        assert_eq!(locate(text, &[field("default_vault")], false), (0, "main"));
+    }
+
+    #[test]
+    fn locates_the_deepest_step_found() {
+        let text = "vaults:\n  - name: main\n";
+        // Past the end of the sequence: the key holding it.
+        let path = [field("vaults"), ConfigKey::Index(5), field("name")];
+        assert_eq!(locate(text, &path, false), (0, "vaults"));
+        let path = [field("vaults"), ConfigKey::Index(0), field("publish_url")];
+        assert_eq!(locate(text, &path, false), (1, "-"));
+    }
+
+    #[test]
+    fn locates_flow_style_values_by_the_key_holding_them() {
+        let text = "vaults: [{name: main, vault_directory: /a}]\n";
+        let path = [field("vaults"), ConfigKey::Index(0), field("name")];
+        assert_eq!(locate(text, &path, false), (0, "vaults"));
+
+        let text = "vaults:\n  - {name: main, vault_directory: /a}\n";
+        let path = [
+            field("vaults"),
+            ConfigKey::Index(0),
+            field("vault_directory"),
+        ];
+        assert_eq!(locate(text, &path, false), (1, "-"));
+    }
+
+    #[test]
+    fn locates_quoted_keys_and_values_on_the_next_line() {
+        let text = "# settings\n\ndefault_vault:\n  main\ncommands:\n  \"greet\": echo hi\n";
+        assert_eq!(
+            locate(text, &[field("default_vault")], false),
+            (2, "default_vault")
+        );
+        let path = [field("commands"), field("greet")];
+        assert_eq!(locate(text, &path, true), (5, "\"greet\""));
+    }
+}
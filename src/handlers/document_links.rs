@@ -0,0 +1,36 @@
+// src/handlers/document_links.rs
+use tower_lsp::lsp_types::*;
+
+use crate::document_store::LineIndex;
+use crate::links::{LinkTarget, find_links, resolve_link};
+use crate::vault_index::VaultIndex;
+
+/// Makes every wiki-link, markdown link, image and bare URL in the document
+/// clickable. Links to notes show the note's title as tooltip and jump to the
+/// heading or block they name; links that cannot be resolved are left out.
+pub fn document_links(uri: &Url, text: &str, index: &VaultIndex) -> Vec<DocumentLink> {
+    let line_index = LineIndex::new(text);
+    find_links(text)
+        .into_iter()
+        .filter_map(|link| {
+            let (target, tooltip) = match resolve_link(&link, uri, index)? {
+                LinkTarget::Note { path, anchor } => {
+                    let note = index.get(&path)?;
+                    let mut target = index.note_uri(&path)?;
+                    if let Some(line) = anchor.and_then(|a| note.anchor_line(&a)) {
+                        target.set_fragment(Some(&format!("L{}", line + 1)));
+                    }
+                    (target, Some(note.title.clone()))
+                }
+                LinkTarget::File(path) => (Url::from_file_path(&path).ok()?, None),
+                LinkTarget::Url(url) => (url, None),
+            };
+            Some(DocumentLink {
+                range: line_index.range(link.dest_span.unwrap_or(link.span)),
+                target: Some(target),
+                tooltip,
+                data: None,
+            })
+        })
+        .collect()
+}
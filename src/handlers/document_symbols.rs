@@ -1,52 +1,130 @@
-use lsp_types::{DocumentSymbol, Position, Range, SymbolKind};
+use lsp_types::{DocumentSymbol, SymbolKind};
 
-/// Scans the given text for markdown headings (lines starting with 1–6 '#' characters)
-/// and returns a vector of DocumentSymbols.
+use crate::document_store::LineIndex;
+use crate::outline::{Block, BlockKind, parse_blocks, section_end, trim_end};
+
+/// Builds the document outline from a markdown parse of the text. Each heading's
+/// range spans its whole section, and lower-level headings, frontmatter, fenced
+/// code blocks, tables and footnote definitions inside it become its children.
 pub fn document_symbols(text: &str) -> Vec<DocumentSymbol> {
-    let mut symbols = Vec::new();
-
-    for (i, line) in text.lines().enumerate() {
-        let trimmed = line.trim_start();
-        if trimmed.starts_with('#') {
-            // Count '#' characters to determine the heading level.
-            let level = trimmed.chars().take_while(|&c| c == '#').count();
-            if level >= 1 && level <= 6 {
-                // Extract heading text by removing the '#' characters and trimming whitespace.
-                let heading = trimmed[level..].trim().to_string();
-                // Create a DocumentSymbol for the heading.
-                let symbol = DocumentSymbol {
-                    name: heading,
-                    detail: None,
-                    // Use the Namespace kind to represent a markdown heading.
-                    kind: SymbolKind::NAMESPACE,
-                    range: Range {
-                        start: Position {
-                            line: i as u32,
-                            character: 0,
-                        },
-                        end: Position {
-                            line: i as u32,
-                            character: line.len() as u32,
-                        },
-                    },
-                    selection_range: Range {
-                        start: Position {
-                            line: i as u32,
-                            character: 0,
-                        },
-                        end: Position {
-                            line: i as u32,
-                            character: line.len() as u32,
-                        },
-                    },
-                    children: None,
-                    // New required fields in lsp_types 0.93:
-                    deprecated: None,
-                    tags: None,
-                };
-                symbols.push(symbol);
+    let line_index = LineIndex::new(text);
+    let blocks = parse_blocks(text);
+
+    let mut roots = Vec::new();
+    // Open heading sections, innermost last.
+    let mut stack: Vec<(usize, DocumentSymbol)> = Vec::new();
+
+    for (i, block) in blocks.iter().enumerate() {
+        let level = match &block.kind {
+            BlockKind::Heading { level, .. } => *level,
+            _ => {
+                if let Some(symbol) = block_symbol(block, block.span.clone(), &line_index, text) {
+                    attach(&mut stack, &mut roots, symbol);
+                }
+                continue;
             }
+        };
+
+        // Close sections that this heading ends.
+        while stack.last().is_some_and(|(l, _)| *l >= level) {
+            let (_, done) = stack.pop().unwrap();
+            attach(&mut stack, &mut roots, done);
+        }
+
+        let section = block.span.start..section_end(text, &blocks, i);
+        if let Some(symbol) = block_symbol(block, section, &line_index, text) {
+            stack.push((level, symbol));
+        }
+    }
+    while let Some((_, done)) = stack.pop() {
+        attach(&mut stack, &mut roots, done);
+    }
+    roots
+}
+
+fn attach(
+    stack: &mut [(usize, DocumentSymbol)],
+    roots: &mut Vec<DocumentSymbol>,
+    symbol: DocumentSymbol,
+) {
+    match stack.last_mut() {
+        Some((_, parent)) => parent.children.get_or_insert_with(Vec::new).push(symbol),
+        None => roots.push(symbol),
+    }
+}
+
+fn block_symbol(
+    block: &Block,
+    span: std::ops::Range<usize>,
+    line_index: &LineIndex,
+    text: &str,
+) -> Option<DocumentSymbol> {
+    let (name, kind) = match &block.kind {
+        // Use the Namespace kind to represent a markdown heading.
+        BlockKind::Heading { level, text } => {
+            // Symbol names must not be empty.
+            let name = if text.is_empty() {
+                "#".repeat(*level)
+            } else {
+                text.clone()
+            };
+            (name, SymbolKind::NAMESPACE)
         }
+        BlockKind::Frontmatter => ("Frontmatter".to_string(), SymbolKind::KEY),
+        BlockKind::CodeBlock { language } => (
+            match language {
+                Some(language) => format!("Code block ({})", language),
+                None => "Code block".to_string(),
+            },
+            SymbolKind::OBJECT,
+        ),
+        BlockKind::Table => ("Table".to_string(), SymbolKind::ARRAY),
+        BlockKind::FootnoteDefinition { label } => (format!("[^{}]", label), SymbolKind::STRING),
+        // Too fine-grained for the outline; these only matter for folding.
+        BlockKind::ListItem | BlockKind::BlockQuote | BlockKind::HtmlComment => return None,
+    };
+    Some(DocumentSymbol {
+        name,
+        detail: None,
+        kind,
+        range: line_index.range(trim_end(text, span)),
+        // Select the block's own text (for headings, just the heading line).
+        selection_range: line_index.range(trim_end(text, block.span.clone())),
+        children: None,
+        // New required fields in lsp_types 0.93:
+        deprecated: None,
+        tags: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = "# A\n\n```\n# not a heading\n```\n\n## B\n\n### C\n\ntext\n";
+
+    /// Symbol names as a tree, e.g. `A(B(C))`.
+    fn outline(symbols: &[DocumentSymbol]) -> String {
+        symbols
+            .iter()
+            .map(|s| match &s.children {
+                Some(children) => format!("{}({})", s.name, outline(children)),
+                None => s.name.clone(),
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    #[test]
+    fn headings_in_code_blocks_are_not_symbols() {
+        assert_eq!(outline(&document_symbols(TEXT)), "A(Code block, B(C))");
+    }
+
+    #[test]
+    fn sections_span_their_subsections() {
+        let symbols = document_symbols(TEXT);
+        let b = &symbols[0].children.as_ref().unwrap()[1];
+        assert_eq!((b.range.start.line, b.range.end.line), (6, 10));
+        assert_eq!(b.selection_range.end.line, 6);
     }
-    symbols
 }
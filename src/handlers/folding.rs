@@ -0,0 +1,53 @@
+// src/handlers/folding.rs
+use tower_lsp::lsp_types::{FoldingRange, FoldingRangeKind};
+
+use crate::document_store::LineIndex;
+use crate::outline::{BlockKind, parse_blocks, section_end, trim_end};
+
+/// Folding ranges for heading sections, frontmatter, fenced code blocks, list items
+/// spanning several lines, block quotes (and callouts) and HTML comments.
+pub fn folding_ranges(text: &str) -> Vec<FoldingRange> {
+    let line_index = LineIndex::new(text);
+    let blocks = parse_blocks(text);
+    let mut ranges = Vec::new();
+
+    for (i, block) in blocks.iter().enumerate() {
+        let (span, kind) = match &block.kind {
+            BlockKind::Heading { .. } => (block.span.start..section_end(text, &blocks, i), None),
+            BlockKind::Frontmatter => (block.span.clone(), Some(FoldingRangeKind::Region)),
+            BlockKind::HtmlComment => (block.span.clone(), Some(FoldingRangeKind::Comment)),
+            BlockKind::CodeBlock { .. }
+            | BlockKind::Table
+            | BlockKind::FootnoteDefinition { .. }
+            | BlockKind::ListItem
+            | BlockKind::BlockQuote => (block.span.clone(), None),
+        };
+
+        let range = line_index.range(trim_end(text, span));
+        if range.end.line > range.start.line {
+            ranges.push(FoldingRange {
+                start_line: range.start.line,
+                end_line: range.end.line,
+                kind,
+                ..Default::default()
+            });
+        }
+    }
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn headings_in_code_blocks_do_not_fold() {
+        let text = "# A\n\n```\n# not a heading\n```\n\n## B\n\n### C\n\ntext\n";
+        let folds: Vec<(u32, u32)> = folding_ranges(text)
+            .iter()
+            .map(|f| (f.start_line, f.end_line))
+            .collect();
+        // A, the code block, B and C within B.
+        assert_eq!(folds, [(0, 10), (2, 4), (6, 10), (8, 10)]);
+    }
+}
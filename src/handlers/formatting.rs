@@ -1,12 +1,12 @@
 // src/handlers/formatting.rs
 
 use pulldown_cmark::{CowStr, Event, LinkType, Options, Parser, Tag, TagEnd};
-use pulldown_cmark_to_cmark::cmark;
+use pulldown_cmark_to_cmark::cmark_with_options;
 use std::borrow::Cow;
-use tower_lsp::lsp_types::Url;
 
-// Import our custom commands module.
-use crate::handlers::custom_commands;
+use crate::handlers::custom_commands::parse_command_syntax;
+use crate::outline::code_block_lines;
+use crate::settings::FormatterSettings;
 
 /// An iterator adapter that transforms WikiLink events into Obsidian‑style links.
 pub struct WikiLinkTransformer<I> {
@@ -101,14 +101,9 @@ where
     }
 }
 
-/// Formats the provided markdown text.
-/// First it processes any custom workspace commands (lines starting with "%%"),
-/// then it parses and formats the markdown using pulldown-cmark.
-/// The file_uri is used to resolve file paths for the commands.
-pub fn format_markdown(text: &str, file_uri: &Url) -> Result<String, String> {
-    // Process and execute any custom commands, and remove them from the text.
-    let processed_text = custom_commands::process_custom_commands(text, file_uri)?;
-
+/// The pulldown-cmark extensions enabled for notes. Shared by the formatter and
+/// every handler that parses markdown so they all agree on the syntax.
+pub fn markdown_options() -> Options {
     let mut options = Options::empty();
     options.insert(Options::ENABLE_TABLES);
     options.insert(Options::ENABLE_FOOTNOTES);
@@ -118,11 +113,42 @@ pub fn format_markdown(text: &str, file_uri: &Url) -> Result<String, String> {
     options.insert(Options::ENABLE_YAML_STYLE_METADATA_BLOCKS);
     options.insert(Options::ENABLE_MATH);
     options.insert(Options::ENABLE_WIKILINKS);
+    options
+}
+
+/// Stands in for a `%%` line while formatting. Letters and digits only, so the
+/// formatter neither escapes nor rewraps it.
+fn placeholder(i: usize) -> String {
+    format!("NotemancyCommandLine{}End", i)
+}
 
-    let parser = Parser::new_ext(&processed_text, options);
+/// Formats the provided markdown text: parses it with pulldown-cmark and renders
+/// it back with the formatter settings. `%%` lines are kept exactly as written;
+/// running commands is up to the caller.
+pub fn format_markdown(text: &str, settings: &FormatterSettings) -> Result<String, String> {
+    let mut kept = Vec::new();
+    let in_code = code_block_lines(text);
+    let protected: Vec<String> = text
+        .lines()
+        .enumerate()
+        .map(|(i, line)| {
+            if in_code.get(i).copied().unwrap_or(false) || parse_command_syntax(line).is_none() {
+                return line.to_string();
+            }
+            kept.push(line.trim());
+            placeholder(kept.len() - 1)
+        })
+        .collect();
+    let protected = protected.join("\n");
+
+    let parser = Parser::new_ext(&protected, markdown_options());
     let transformed = WikiLinkTransformer::new(parser);
     let mut formatted = String::new();
-    cmark(transformed, &mut formatted).map_err(|e| e.to_string())?;
-    let formatted = formatted.replace(r"\[[", "[[");
+    cmark_with_options(transformed, &mut formatted, settings.cmark_options())
+        .map_err(|e| e.to_string())?;
+    let mut formatted = formatted.replace(r"\[[", "[[");
+    for (i, line) in kept.iter().enumerate() {
+        formatted = formatted.replace(&placeholder(i), line);
+    }
     Ok(formatted)
 }
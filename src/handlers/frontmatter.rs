@@ -0,0 +1,221 @@
+// src/handlers/frontmatter.rs
+use std::ops::Range as LineRange;
+use tower_lsp::lsp_types::*;
+
+use crate::config::{FieldSchema, FrontmatterSchema};
+use crate::document_store::{byte_to_utf16, line_ending, utf16_to_byte};
+
+/// Line numbers of the YAML between the opening and closing `---` of the
+/// frontmatter block, or `None` if the document has no (closed) block.
+pub fn frontmatter_lines(text: &str) -> Option<LineRange<usize>> {
+    let mut lines = text.lines();
+    if lines.next().map(str::trim_end) != Some("---") {
+        return None;
+    }
+    let close = lines.position(|l| l.trim_end() == "---")?;
+    Some(1..close + 1)
+}
+
+/// The top-level key defined on a frontmatter line (`key: value`), if any.
+pub fn line_key(line: &str) -> Option<&str> {
+    if line.starts_with(char::is_whitespace) || line.starts_with('-') || line.starts_with('#') {
+        return None;
+    }
+    let (key, _) = line.split_once(':')?;
+    Some(key.trim())
+}
+
+/// Sets a top-level frontmatter key to a YAML value, replacing its current value
+/// (including a block list under it) or adding it at the end of the block.
+/// A document without frontmatter gets a new block.
+pub fn set_key(text: &str, key: &str, value: &str) -> String {
+    let mut lines: Vec<String> = text.lines().map(str::to_string).collect();
+    let new_line = format!("{}: {}", key, value);
+    match frontmatter_lines(text) {
+        Some(block) => match block.clone().find(|&i| line_key(&lines[i]) == Some(key)) {
+            Some(i) => {
+                // The value continues on indented lines and list items.
+                let end = (i + 1..block.end)
+                    .find(|&j| !lines[j].starts_with([' ', '\t', '-']))
+                    .unwrap_or(block.end);
+                lines.splice(i..end, [new_line]);
+            }
+            None => lines.insert(block.end, new_line),
+        },
+        None => {
+            lines.splice(0..0, ["---".to_string(), new_line, "---".to_string()]);
+        }
+    }
+    let newline = line_ending(text);
+    let mut result = lines.join(newline);
+    if text.ends_with('\n') || text.is_empty() {
+        result.push_str(newline);
+    }
+    result
+}
+
+/// Adds a value to a frontmatter list such as `tags`, unless it is already there.
+/// A single string value is turned into a list.
+pub fn add_list_value(text: &str, key: &str, value: &str) -> String {
+    let current = frontmatter_lines(text).and_then(|block| {
+        let yaml = text.lines().collect::<Vec<_>>()[block].join("\n");
+        let frontmatter: serde_yaml::Value = serde_yaml::from_str(&yaml).ok()?;
+        frontmatter.get(key).cloned()
+    });
+    let mut values: Vec<String> = match current {
+        Some(serde_yaml::Value::Sequence(items)) => items
+            .iter()
+            .filter_map(|v| v.as_str().map(str::to_string))
+            .collect(),
+        Some(serde_yaml::Value::String(s)) => vec![s],
+        _ => Vec::new(),
+    };
+    if values.iter().any(|v| v == value) {
+        return text.to_string();
+    }
+    values.push(value.to_string());
+    set_key(text, key, &format!("[{}]", values.join(", ")))
+}
+
+/// Completes keys from the vault's frontmatter schema at the start of a line in
+/// the frontmatter block, and allowed values after a key or in its list items.
+pub fn frontmatter_completions(
+    text: &str,
+    position: Position,
+    schema: &FrontmatterSchema,
+) -> Option<CompletionResponse> {
+    let block = frontmatter_lines(text)?;
+    let line_no = position.line as usize;
+    if schema.is_empty() || !block.contains(&line_no) {
+        return None;
+    }
+    let lines: Vec<&str> = text.lines().collect();
+    let line = lines[line_no];
+    let cursor = utf16_to_byte(line, position.character);
+    let before = &line[..cursor];
+    let range_from = |start: usize| Range {
+        start: Position {
+            line: position.line,
+            character: byte_to_utf16(line, start),
+        },
+        end: position,
+    };
+
+    let indented = before.starts_with(char::is_whitespace) || before.starts_with('-');
+    if !indented && !before.contains(':') {
+        // Typing a key: offer the schema keys the block does not define yet.
+        let used: Vec<&str> = lines[block].iter().filter_map(|l| line_key(l)).collect();
+        let range = range_from(0);
+        let items = schema
+            .iter()
+            .filter(|(key, _)| !used.contains(&key.as_str()))
+            .map(|(key, field)| CompletionItem {
+                label: key.clone(),
+                kind: Some(CompletionItemKind::PROPERTY),
+                detail: Some(field_summary(field)),
+                documentation: field.description.clone().map(Documentation::String),
+                text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                    range,
+                    new_text: format!("{}: ", key),
+                })),
+                ..Default::default()
+            })
+            .collect();
+        return Some(CompletionResponse::Array(items));
+    }
+
+    // Typing a value: find the key it belongs to, on this line or the nearest
+    // unindented line above for block lists.
+    let (key, value_start) = if indented {
+        let key = lines[block.start..line_no]
+            .iter()
+            .rev()
+            .find_map(|l| line_key(l))?;
+        let trimmed = before.trim_start();
+        let offset = before.len() - trimmed.len();
+        (key, offset + if trimmed.starts_with('-') { 1 } else { 0 })
+    } else {
+        let colon = before.find(':')?;
+        (before[..colon].trim(), colon + 1)
+    };
+    let field = schema.get(key)?;
+    if field.values.is_empty() {
+        return None;
+    }
+    // Inline lists (`[a, b]` or `a, b`) complete the item after the last separator.
+    let token_start = before[value_start..]
+        .rfind([',', '['])
+        .map(|i| value_start + i + 1)
+        .unwrap_or(value_start);
+    let token_start =
+        token_start + (before[token_start..].len() - before[token_start..].trim_start().len());
+    let range = range_from(token_start);
+    let items = field
+        .values
+        .iter()
+        .map(|value| CompletionItem {
+            label: value.clone(),
+            kind: Some(CompletionItemKind::ENUM_MEMBER),
+            detail: Some(key.to_string()),
+            text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                range,
+                new_text: value.clone(),
+            })),
+            ..Default::default()
+        })
+        .collect();
+    Some(CompletionResponse::Array(items))
+}
+
+/// Shows the schema documentation for the frontmatter key under the cursor.
+pub fn frontmatter_hover(
+    text: &str,
+    position: Position,
+    schema: &FrontmatterSchema,
+) -> Option<Hover> {
+    let block = frontmatter_lines(text)?;
+    if !block.contains(&(position.line as usize)) {
+        return None;
+    }
+    let line = text.lines().nth(position.line as usize)?;
+    let key = line_key(line)?;
+    let field = schema.get(key)?;
+    let key_start = line.find(key)?;
+    let cursor = utf16_to_byte(line, position.character);
+    if cursor < key_start || cursor > key_start + key.len() {
+        return None;
+    }
+
+    let mut sections = vec![format!("**{}** — {}", key, field_summary(field))];
+    if let Some(description) = &field.description {
+        sections.push(description.clone());
+    }
+    if !field.values.is_empty() {
+        let values: Vec<String> = field.values.iter().map(|v| format!("`{}`", v)).collect();
+        sections.push(format!("Allowed values: {}", values.join(", ")));
+    }
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value: sections.join("\n\n"),
+        }),
+        range: Some(Range {
+            start: Position {
+                line: position.line,
+                character: byte_to_utf16(line, key_start),
+            },
+            end: Position {
+                line: position.line,
+                character: byte_to_utf16(line, key_start + key.len()),
+            },
+        }),
+    })
+}
+
+fn field_summary(field: &FieldSchema) -> String {
+    if field.required {
+        format!("{}, required", field.kind.name())
+    } else {
+        field.kind.name().to_string()
+    }
+}
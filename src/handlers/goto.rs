@@ -1,79 +1,89 @@
-use crate::handlers::completion::get_vault_directory;
-use regex::Regex;
+use crate::document_store::utf16_to_byte;
+use crate::links::{DocLinkKind, LinkTarget, link_at, resolve_link};
+use crate::vault_index::{NoteEntry, VaultIndex};
+use crate::wikilink::{split_anchor, wikilink_at};
 use tower_lsp::lsp_types::*;
 
 /// Attempts to resolve a wiki-link at the current position.
 /// It looks for a pattern like:
-///   [[ relative_path | title ]]
-/// where whitespace is optional.
-/// error messages are logged to help trace the computed path.
-pub fn goto_wikilink(document_text: &str, position: Position) -> Option<Location> {
-    let lines: Vec<&str> = document_text.lines().collect();
-    if (position.line as usize) >= lines.len() {
-        eprintln!(
-            "Position.line {} out of range ({} total lines)",
-            position.line,
-            lines.len()
-        );
+///   [[ relative_path#anchor | title ]]
+/// where whitespace, the anchor and the title are optional. The anchor may name a
+/// heading (`#Some Heading`) or a block (`#^block-id`); the location points at that line.
+pub fn goto_wikilink(
+    uri: &Url,
+    document_text: &str,
+    position: Position,
+    index: &VaultIndex,
+) -> Option<Location> {
+    let line = document_text.lines().nth(position.line as usize)?;
+    let cursor = utf16_to_byte(line, position.character);
+    let link = wikilink_at(line, cursor)?;
+
+    let (relative_path, anchor) = split_anchor(link.path);
+    if relative_path.is_empty() && anchor.is_none() {
         return None;
     }
-    let line = lines[position.line as usize];
 
-    // Regex with named capture groups for path and optional title.
-    let re = Regex::new(r"\[\[\s*(?P<path>[^|\]]+?)\s*(?:\|\s*(?P<title>[^\]]+?)\s*)?\]\]").ok()?;
-    for caps in re.captures_iter(line) {
-        let mat = caps.get(0)?;
-        let start = mat.start();
-        let end = mat.end();
+    // `[[#Heading]]` points into the current document.
+    let current;
+    let (target_uri, note) = if relative_path.is_empty() {
+        current = NoteEntry::parse("", document_text);
+        (uri.clone(), Some(&current))
+    } else {
+        let note = index.resolve(relative_path);
+        let path = note.map(|n| n.path.as_str()).unwrap_or(relative_path);
+        (index.note_uri(path)?, note)
+    };
 
-        if (position.character as usize) >= start && (position.character as usize) <= end {
-            let relative_path = caps.name("path")?.as_str().trim();
-            if relative_path.is_empty() {
-                return None;
-            }
-            let vault_dir = match get_vault_directory() {
-                Ok(dir) => dir,
-                Err(err) => {
-                    eprintln!("Failed to get vault directory: {}", err);
-                    return None;
-                }
-            };
+    let target_line = anchor
+        .zip(note)
+        .and_then(|(anchor, note)| note.anchor_line(anchor))
+        .unwrap_or(0);
 
-            let abs_path = vault_dir.join(relative_path);
-            let uri = match Url::from_file_path(&abs_path) {
-                Ok(u) => u,
-                Err(()) => {
-                    eprintln!(
-                        "Failed to create file URI from path: {}",
-                        abs_path.display()
-                    );
-                    return None;
-                }
-            };
+    Some(Location {
+        uri: target_uri,
+        range: Range {
+            start: Position {
+                line: target_line,
+                character: 0,
+            },
+            end: Position {
+                line: target_line,
+                character: 0,
+            },
+        },
+    })
+}
 
-            return Some(Location {
-                uri,
-                range: Range {
-                    start: Position {
-                        line: 0,
-                        character: 0,
-                    },
-                    end: Position {
-                        line: 0,
-                        character: 0,
-                    },
-                },
-            });
-        } else {
-            eprintln!(
-                "Cursor position {} not within match range {}-{}",
-                position.character, start, end
-            );
-        }
+/// Resolves a markdown link or image under the cursor: inline (`[text](../other.md#anchor)`)
+/// or reference-style (`[text][ref]`), relative to the current file or, with a leading
+/// `/`, to the vault root. Links to notes jump to their anchor; other local files open
+/// at the top.
+pub fn goto_markdown_link(
+    uri: &Url,
+    document_text: &str,
+    position: Position,
+    index: &VaultIndex,
+) -> Option<Location> {
+    let link = link_at(document_text, position)?;
+    if !matches!(link.kind, DocLinkKind::Markdown | DocLinkKind::Image) {
+        return None;
     }
-    eprintln!(
-        "No matching wiki-link found at cursor position {}.",
-        position.character
-    );
-    None
+    let (target_uri, line) = match resolve_link(&link, uri, index)? {
+        LinkTarget::Note { path, anchor } => {
+            let note = index.get(&path)?;
+            let line = anchor.and_then(|a| note.anchor_line(&a)).unwrap_or(0);
+            (index.note_uri(&path)?, line)
+        }
+        LinkTarget::File(path) => (Url::from_file_path(path).ok()?, 0),
+        LinkTarget::Url(_) => return None,
+    };
+    let position = Position { line, character: 0 };
+    Some(Location {
+        uri: target_uri,
+        range: Range {
+            start: position,
+            end: position,
+        },
+    })
 }
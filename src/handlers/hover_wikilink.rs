@@ -1,58 +1,96 @@
-use crate::handlers::completion::get_vault_directory;
-use regex::Regex;
-use std::fs;
+use crate::document_store::{LineIndex, byte_to_utf16, utf16_to_byte};
+use crate::links::{DocLinkKind, LinkTarget, link_at, resolve_link};
+use crate::vault_index::{NoteEntry, VaultIndex};
+use crate::wikilink::{split_anchor, wikilink_at};
 use tower_lsp::lsp_types::*;
 
 /// Provides a hover preview for a wiki-link.
 /// When the cursor is over a wiki-link, this function extracts the relative path,
-/// joins it with the vault directory, reads the file content, and returns a Hover
-/// containing the full file content.
-pub fn hover_wikilink(document_text: &str, position: Position) -> Option<Hover> {
+/// looks the note up in the vault index, and returns a Hover
+/// containing the full note content. When the link has a `#heading` or `#^block-id`
+/// anchor, only that section of the note is shown.
+pub fn hover_wikilink(
+    document_text: &str,
+    position: Position,
+    index: &VaultIndex,
+) -> Option<Hover> {
     let lines: Vec<&str> = document_text.lines().collect();
     if (position.line as usize) >= lines.len() {
         return None;
     }
     let line = lines[position.line as usize];
+    let cursor = utf16_to_byte(line, position.character);
 
-    // Regex with named capture groups:
-    // - "path": captures the relative path (up to the first '|' or closing brackets)
-    // - "title": optionally captures the title if provided.
-    let re = Regex::new(r"\[\[\s*(?P<path>[^|\]]+?)\s*(?:\|\s*(?P<title>[^\]]+?)\s*)?\]\]").ok()?;
-    for caps in re.captures_iter(line) {
-        let mat = caps.get(0)?;
-        let start = mat.start();
-        let end = mat.end();
-        // Check if the cursor falls within the wiki-link match.
-        if (position.character as usize) >= start && (position.character as usize) <= end {
-            let relative_path = caps.name("path")?.as_str().trim();
-            if relative_path.is_empty() {
-                return None;
+    // Find the wiki-link (if any) under the cursor.
+    let link = wikilink_at(line, cursor)?;
+    let (relative_path, anchor) = split_anchor(link.path);
+    let current;
+    let note = if relative_path.is_empty() {
+        // `[[#Heading]]` points into the current document.
+        anchor?;
+        current = NoteEntry::parse("", document_text);
+        &current
+    } else {
+        index.resolve(relative_path)?
+    };
+    let value = match anchor.filter(|a| !a.is_empty()) {
+        Some(anchor) => note.anchor_section(anchor)?,
+        None => note.content.clone(),
+    };
+    // Create a Hover with the note (or section) content as Markdown.
+    let hover_contents = HoverContents::Markup(MarkupContent {
+        kind: MarkupKind::Markdown,
+        value,
+    });
+    Some(Hover {
+        contents: hover_contents,
+        // Mark the range corresponding to the wiki-link in the document.
+        range: Some(Range {
+            start: Position {
+                line: position.line,
+                character: byte_to_utf16(line, link.start),
+            },
+            end: Position {
+                line: position.line,
+                character: byte_to_utf16(line, link.end),
+            },
+        }),
+    })
+}
+
+/// Provides a hover preview for a markdown link or image, resolved like
+/// `goto_markdown_link`. Links to notes show the note (or the section the anchor
+/// names); images, local or remote, are rendered.
+pub fn hover_markdown_link(
+    uri: &Url,
+    document_text: &str,
+    position: Position,
+    index: &VaultIndex,
+) -> Option<Hover> {
+    let link = link_at(document_text, position)?;
+    if !matches!(link.kind, DocLinkKind::Markdown | DocLinkKind::Image) {
+        return None;
+    }
+    let target = resolve_link(&link, uri, index)?;
+    let value = match (link.kind, target) {
+        (_, LinkTarget::Note { path, anchor }) => {
+            let note = index.get(&path)?;
+            match anchor {
+                Some(anchor) => note.anchor_section(&anchor)?,
+                None => note.content.clone(),
             }
-            // Join the relative path with the vault directory.
-            let vault_dir = get_vault_directory().ok()?;
-            let abs_path = vault_dir.join(relative_path);
-            // Read the entire file content.
-            let file_content = fs::read_to_string(&abs_path).ok()?;
-            // Create a Hover with the full file content as Markdown.
-            let hover_contents = HoverContents::Markup(MarkupContent {
-                kind: MarkupKind::Markdown,
-                value: file_content,
-            });
-            return Some(Hover {
-                contents: hover_contents,
-                // Mark the range corresponding to the wiki-link in the document.
-                range: Some(Range {
-                    start: Position {
-                        line: position.line,
-                        character: start as u32,
-                    },
-                    end: Position {
-                        line: position.line,
-                        character: end as u32,
-                    },
-                }),
-            });
         }
-    }
-    None
+        (DocLinkKind::Image, LinkTarget::File(path)) => {
+            format!("![{}]({})", link.dest, Url::from_file_path(path).ok()?)
+        }
+        (DocLinkKind::Image, LinkTarget::Url(url)) => format!("![{}]({})", link.dest, url),
+        _ => return None,
+    };
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value,
+        }),
+        range: Some(LineIndex::new(document_text).range(link.span)),
+    })
 }
@@ -1,8 +1,18 @@
 // src/handlers/mod.rs
+pub mod code_actions;
 pub mod completion;
 pub mod custom_commands;
+pub mod diagnostics;
+pub mod document_links;
 pub mod document_symbols;
+pub mod folding;
 pub mod formatting;
+pub mod frontmatter;
 pub mod goto;
 pub mod hover_wikilink;
+pub mod references;
+pub mod rename;
+pub mod semantic_tokens;
+pub mod tags;
+pub mod workspace_commands;
 pub mod workspace_symbols;
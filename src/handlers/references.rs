@@ -0,0 +1,84 @@
+// src/handlers/references.rs
+use tower_lsp::lsp_types::*;
+
+use crate::vault_index::{NoteEntry, VaultIndex};
+use crate::wikilink::anchor_matches_heading;
+
+/// Finds every link in the vault that targets the current note. When the cursor
+/// is on a heading, only links to that heading are returned.
+pub fn find_references(
+    uri: &Url,
+    document_text: &str,
+    position: Position,
+    include_declaration: bool,
+    index: &VaultIndex,
+) -> Vec<Location> {
+    let Some(note_path) = uri
+        .to_file_path()
+        .ok()
+        .and_then(|path| index.relative_path(&path))
+    else {
+        return Vec::new();
+    };
+
+    // Parse the buffer so the heading lookup matches what the user sees.
+    let current = NoteEntry::parse(&note_path, document_text);
+    let heading = current.headings.iter().find(|h| h.line == position.line);
+
+    let mut locations = Vec::new();
+    if include_declaration {
+        let line = heading.map(|h| h.line).unwrap_or(0);
+        locations.push(Location {
+            uri: uri.clone(),
+            range: Range {
+                start: Position { line, character: 0 },
+                end: Position { line, character: 0 },
+            },
+        });
+    }
+
+    for (source, link) in index.backlinks(&note_path) {
+        if let Some(heading) = heading {
+            let matches_heading = link
+                .anchor
+                .as_deref()
+                .is_some_and(|anchor| anchor_matches_heading(anchor, &heading.text));
+            if !matches_heading {
+                continue;
+            }
+        }
+        if let Some(uri) = index.note_uri(&source.path) {
+            locations.push(Location {
+                uri,
+                range: link.range,
+            });
+        }
+    }
+
+    locations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn references_include_links_through_aliases() {
+        let note = "---\naliases: [My Alias]\n---\n# Note\n";
+        let mut index = VaultIndex::empty(PathBuf::from("/vault"));
+        index.update_note("real/note.md", note);
+        index.update_note("a.md", "[[My Alias]] and [[real/note]]\n");
+        index.update_note("b.md", "[[my alias#Note]] and [[Other]]\n");
+        let uri = Url::from_file_path("/vault/real/note.md").unwrap();
+        let locations = find_references(&uri, note, Position::new(0, 0), false, &index);
+        let found: Vec<(&str, u32)> = locations
+            .iter()
+            .map(|l| (l.uri.path(), l.range.start.character))
+            .collect();
+        assert_eq!(
+            found,
+            [("/vault/a.md", 0), ("/vault/a.md", 17), ("/vault/b.md", 0)]
+        );
+    }
+}
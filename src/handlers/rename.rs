@@ -0,0 +1,485 @@
+// src/handlers/rename.rs
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
+use tower_lsp::lsp_types::*;
+
+use crate::document_store::{DocumentStore, byte_to_utf16, utf16_to_byte};
+use crate::vault_index::{
+    LinkKind, NoteEntry, NoteLink, VaultIndex, VaultSet, normalize_relative, percent_decode,
+};
+use crate::wikilink::{split_anchor, wikilink_at};
+
+/// The note a rename at the cursor applies to, and the range the editor should
+/// highlight while the user types the new name.
+struct RenameTarget {
+    note_path: String,
+    range: Range,
+    /// The text in `range`, offered as the name to edit.
+    placeholder: String,
+    /// Whether the rename started from the note's H1 or title, where the new name
+    /// is a title rather than a path.
+    from_title: bool,
+}
+
+/// Finds what can be renamed at the cursor: either the note a wiki-link points at,
+/// or the current note itself when the cursor is on its H1 or frontmatter title.
+fn rename_target(
+    uri: &Url,
+    document_text: &str,
+    position: Position,
+    index: &VaultIndex,
+) -> Option<RenameTarget> {
+    let line = document_text.lines().nth(position.line as usize)?;
+    let cursor = utf16_to_byte(line, position.character);
+
+    if let Some(link) = wikilink_at(line, cursor) {
+        let (path, _) = split_anchor(link.path);
+        let note = index.resolve(path)?;
+        let start = link.path_start + link.path.find(path)?;
+        return Some(RenameTarget {
+            note_path: note.path.clone(),
+            range: Range {
+                start: Position {
+                    line: position.line,
+                    character: byte_to_utf16(line, start),
+                },
+                end: Position {
+                    line: position.line,
+                    character: byte_to_utf16(line, start + path.len()),
+                },
+            },
+            placeholder: note.path.clone(),
+            from_title: false,
+        });
+    }
+
+    let note_path = index.relative_path(&uri.to_file_path().ok()?)?;
+    let current = NoteEntry::parse(&note_path, document_text);
+    let on_h1 = current
+        .headings
+        .iter()
+        .any(|h| h.level == 1 && h.line == position.line);
+    let on_title = line.trim_start().starts_with("title:") && current.frontmatter.is_some();
+    if !on_h1 && !on_title {
+        return None;
+    }
+    let text_start = if on_h1 {
+        line.len()
+            - line
+                .trim_start_matches(|c: char| c == '#' || c.is_whitespace())
+                .len()
+    } else {
+        let value_start = line.find(':')? + 1;
+        let value = &line[value_start..];
+        value_start + (value.len() - value.trim_start().len())
+    };
+    let text_end = line.trim_end().len();
+    Some(RenameTarget {
+        note_path,
+        range: Range {
+            start: Position {
+                line: position.line,
+                character: byte_to_utf16(line, text_start),
+            },
+            end: Position {
+                line: position.line,
+                character: byte_to_utf16(line, text_end),
+            },
+        },
+        placeholder: line[text_start..text_end].to_string(),
+        from_title: true,
+    })
+}
+
+pub fn prepare_rename(
+    uri: &Url,
+    document_text: &str,
+    position: Position,
+    index: &VaultIndex,
+) -> Option<PrepareRenameResponse> {
+    let target = rename_target(uri, document_text, position, index)?;
+    Some(PrepareRenameResponse::RangeWithPlaceholder {
+        range: target.range,
+        placeholder: target.placeholder,
+    })
+}
+
+/// Renames the note at the cursor to `new_name` (a vault-relative path, or a bare
+/// name kept in the note's folder when renaming from its H1 or title). The edit
+/// rewrites the path of every inbound link, keeping display titles, and the
+/// relative links of the note itself, and then moves the file. A rename from the
+/// H1 or title also replaces that text with the new name.
+pub fn rename(
+    uri: &Url,
+    document_text: &str,
+    position: Position,
+    new_name: &str,
+    index: &VaultIndex,
+    documents: &DocumentStore,
+) -> Result<Option<WorkspaceEdit>, String> {
+    let Some(target) = rename_target(uri, document_text, position, index) else {
+        return Ok(None);
+    };
+    let mut new_path = normalize_note_name(new_name)?;
+    let folder = Path::new(&target.note_path)
+        .parent()
+        .and_then(Path::to_str)
+        .filter(|folder| !folder.is_empty());
+    if let Some(folder) = folder.filter(|_| target.from_title && !new_path.contains('/')) {
+        new_path = format!("{}/{}", folder, new_path);
+    }
+    if new_path.is_empty() {
+        return Err("New note path is empty".to_string());
+    }
+    if new_path == target.note_path {
+        return Ok(None);
+    }
+    if index.get(&new_path).is_some() {
+        return Err(format!("A note named '{}' already exists", new_path));
+    }
+
+    let old_uri = index
+        .note_uri(&target.note_path)
+        .ok_or_else(|| format!("Invalid note path: {}", target.note_path))?;
+    let new_uri = index
+        .note_uri(&new_path)
+        .ok_or_else(|| format!("Invalid note path: {}", new_path))?;
+
+    let mut edits = link_edits(index, &[(target.note_path.clone(), new_path.clone())]);
+    if target.from_title {
+        let title = Path::new(&new_path)
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or_default()
+            .to_string();
+        let note_edits = edits.entry(target.note_path).or_default();
+        // A link inside the title is replaced along with it.
+        note_edits.retain(|edit| edit.range.start.line != target.range.start.line);
+        note_edits.push(TextEdit {
+            range: target.range,
+            new_text: title,
+        });
+    }
+    let mut operations = text_document_edits(index, documents, edits)
+        .into_iter()
+        .map(DocumentChangeOperation::Edit)
+        .collect::<Vec<_>>();
+    operations.push(DocumentChangeOperation::Op(ResourceOp::Rename(
+        RenameFile {
+            old_uri,
+            new_uri,
+            options: None,
+            annotation_id: None,
+        },
+    )));
+
+    Ok(Some(WorkspaceEdit {
+        document_changes: Some(DocumentChanges::Operations(operations)),
+        ..Default::default()
+    }))
+}
+
+/// Handles `workspace/willRenameFiles`: the client moves the files itself, we
+/// only update the links pointing at them. Renamed directories update every
+/// note below them.
+pub fn will_rename_files(
+    params: &RenameFilesParams,
+    vaults: &VaultSet,
+    documents: &DocumentStore,
+) -> Option<WorkspaceEdit> {
+    let mut operations = Vec::new();
+    for file in &params.files {
+        let to_path = |uri: &str| Url::parse(uri).ok()?.to_file_path().ok();
+        let (Some(old), Some(new)) = (to_path(&file.old_uri), to_path(&file.new_uri)) else {
+            continue;
+        };
+        let Some(index) = vaults.containing(&old) else {
+            continue;
+        };
+        let (Some(old_rel), Some(new_rel)) = (index.relative_path(&old), index.relative_path(&new))
+        else {
+            continue;
+        };
+
+        let mut renames = Vec::new();
+        if index.get(&old_rel).is_some() {
+            renames.push((old_rel, new_rel));
+        } else {
+            let dir_prefix = format!("{}/", old_rel.trim_end_matches('/'));
+            for note in index.notes() {
+                if let Some(rest) = note.path.strip_prefix(&dir_prefix) {
+                    renames.push((
+                        note.path.clone(),
+                        format!("{}/{}", new_rel.trim_end_matches('/'), rest),
+                    ));
+                }
+            }
+        }
+        let edits = link_edits(index, &renames);
+        operations.extend(text_document_edits(index, documents, edits));
+    }
+
+    if operations.is_empty() {
+        return None;
+    }
+    Some(WorkspaceEdit {
+        document_changes: Some(DocumentChanges::Edits(operations)),
+        ..Default::default()
+    })
+}
+
+/// Turns a user-supplied name into a vault-relative `.md` path. Names that leave
+/// the vault or cannot be written inside a wiki-link are rejected.
+fn normalize_note_name(new_name: &str) -> Result<String, String> {
+    let name = new_name.trim();
+    if let Some(bad) = ["#", "|", "]]"].into_iter().find(|bad| name.contains(bad)) {
+        return Err(format!("Note names cannot contain '{}'", bad));
+    }
+    let name = normalize_relative(Path::new(name.trim_start_matches('/')))
+        .ok_or_else(|| format!("'{}' is outside the vault", name))?;
+    if name.is_empty() || name.ends_with(".md") {
+        Ok(name)
+    } else {
+        Ok(format!("{}.md", name))
+    }
+}
+
+/// Collects, per linking note, the edits that retarget links from each old path
+/// to its new path. Only the path part of a link is replaced. Moved notes also get
+/// their own relative markdown links rewritten from their new folder. Edits are
+/// keyed by the notes' current paths, as they apply before the files move.
+pub fn link_edits(
+    index: &VaultIndex,
+    renames: &[(String, String)],
+) -> BTreeMap<String, Vec<TextEdit>> {
+    let moved: HashMap<&str, &str> = renames
+        .iter()
+        .map(|(old, new)| (old.as_str(), new.as_str()))
+        .collect();
+    let new_location = |path: &str| moved.get(path).copied().unwrap_or(path).to_string();
+
+    let mut edits: BTreeMap<String, Vec<TextEdit>> = BTreeMap::new();
+    let mut seen: HashSet<(String, u32, u32)> = HashSet::new();
+    let mut push = |source: &str, link: &NoteLink, new_text: String| {
+        let Some(range) = link.target_range else {
+            return;
+        };
+        let unchanged = match link.kind {
+            LinkKind::Wiki => new_text == link.written_path,
+            LinkKind::Markdown => percent_decode(&new_text) == percent_decode(&link.written_path),
+        };
+        if unchanged || !seen.insert((source.to_string(), range.start.line, range.start.character))
+        {
+            return;
+        }
+        edits
+            .entry(source.to_string())
+            .or_default()
+            .push(TextEdit { range, new_text });
+    };
+
+    for (old_path, new_path) in renames {
+        for (source, link) in index.backlinks(old_path) {
+            let new_text = match link.kind {
+                LinkKind::Wiki => {
+                    let path = if link.written_path.ends_with(".md") {
+                        new_path.clone()
+                    } else {
+                        new_path.trim_end_matches(".md").to_string()
+                    };
+                    // A link through an alias keeps showing the alias.
+                    if link.target != *old_path && link.title.is_none() && link.anchor.is_none() {
+                        format!("{}|{}", path, link.written_path)
+                    } else {
+                        path
+                    }
+                }
+                LinkKind::Markdown => {
+                    if link.written_path.starts_with('/') {
+                        format!("/{}", new_path.replace(' ', "%20"))
+                    } else {
+                        relative_link(&new_location(&source.path), new_path)
+                    }
+                }
+            };
+            push(&source.path, link, new_text);
+        }
+
+        let Some(note) = index.get(old_path) else {
+            continue;
+        };
+        for link in &note.links {
+            if link.kind != LinkKind::Markdown
+                || link.written_path.starts_with('/')
+                || link.target == note.path
+            {
+                continue;
+            }
+            push(
+                &note.path,
+                link,
+                relative_link(new_path, &new_location(&link.target)),
+            );
+        }
+    }
+    edits
+}
+
+/// Turns edits keyed by vault-relative note path into versioned document edits.
+pub fn text_document_edits(
+    index: &VaultIndex,
+    documents: &DocumentStore,
+    edits: BTreeMap<String, Vec<TextEdit>>,
+) -> Vec<TextDocumentEdit> {
+    edits
+        .into_iter()
+        .filter_map(|(path, edits)| {
+            let uri = index.note_uri(&path)?;
+            let version = documents.version(&uri);
+            Some(TextDocumentEdit {
+                text_document: OptionalVersionedTextDocumentIdentifier { uri, version },
+                edits: edits.into_iter().map(OneOf::Left).collect(),
+            })
+        })
+        .collect()
+}
+
+/// Path of `to` relative to the directory containing `from` (both vault-relative),
+/// as a markdown link destination: spaces are written as `%20`.
+fn relative_link(from: &str, to: &str) -> String {
+    let from_dir: Vec<&str> = Path::new(from)
+        .parent()
+        .map(|p| p.to_str().unwrap_or(""))
+        .unwrap_or("")
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .collect();
+    let to_parts: Vec<&str> = to.split('/').collect();
+    let common = from_dir
+        .iter()
+        .zip(&to_parts[..to_parts.len() - 1])
+        .take_while(|(a, b)| a == b)
+        .count();
+    let mut parts: Vec<&str> = vec![".."; from_dir.len() - common];
+    parts.extend(&to_parts[common..]);
+    parts.join("/").replace(' ', "%20")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index(notes: &[(&str, &str)]) -> VaultIndex {
+        let mut index = VaultIndex::default();
+        for (path, content) in notes {
+            index.update_note(path, content);
+        }
+        index
+    }
+
+    fn new_texts<'a>(edits: &'a BTreeMap<String, Vec<TextEdit>>, path: &str) -> Vec<&'a str> {
+        edits
+            .get(path)
+            .map(|edits| edits.iter().map(|e| e.new_text.as_str()).collect())
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn relative_link_between_sibling_and_nested_folders() {
+        assert_eq!(relative_link("a/x.md", "a/y.md"), "y.md");
+        assert_eq!(relative_link("a/x.md", "a/b/y.md"), "b/y.md");
+        assert_eq!(relative_link("a/b/x.md", "a/y.md"), "../y.md");
+        assert_eq!(relative_link("a/x.md", "b/y.md"), "../b/y.md");
+        assert_eq!(relative_link("x.md", "a/b/y.md"), "a/b/y.md");
+        assert_eq!(relative_link("a/b/x.md", "y.md"), "../../y.md");
+    }
+
+    #[test]
+    fn relative_link_escapes_spaces() {
+        assert_eq!(relative_link("x.md", "My Note.md"), "My%20Note.md");
+        assert_eq!(
+            relative_link("My Docs/x.md", "Other Docs/y z.md"),
+            "../Other%20Docs/y%20z.md"
+        );
+    }
+
+    #[test]
+    fn link_edits_retarget_wiki_and_markdown_links() {
+        let index = index(&[
+            ("a/x.md", "[[b/y]] and [Y](../b/y.md)\n"),
+            ("b/y.md", "# Y\n"),
+        ]);
+        let edits = link_edits(&index, &[("b/y.md".into(), "c/d/y.md".into())]);
+        assert_eq!(new_texts(&edits, "a/x.md"), ["c/d/y", "../c/d/y.md"]);
+        assert!(!edits.contains_key("b/y.md"));
+    }
+
+    #[test]
+    fn link_edits_rewrite_the_moved_notes_own_links() {
+        let index = index(&[
+            ("notes/x.md", "[Z](../My%20Docs/z%20z.md)\n"),
+            ("My Docs/z z.md", "[X](../notes/x.md)\n"),
+        ]);
+        let edits = link_edits(&index, &[("notes/x.md".into(), "notes/sub/x.md".into())]);
+        assert_eq!(new_texts(&edits, "My Docs/z z.md"), ["../notes/sub/x.md"]);
+        assert_eq!(
+            new_texts(&edits, "notes/x.md"),
+            ["../../My%20Docs/z%20z.md"]
+        );
+    }
+
+    #[test]
+    fn link_edits_skip_links_that_still_resolve() {
+        let index = index(&[
+            ("notes/x.md", "[Z](../My%20Docs/z%20z.md)\n"),
+            ("My Docs/z z.md", "[X](../notes/x.md)\n"),
+        ]);
+        // Same folder: the note's own `%20` link is already right.
+        let edits = link_edits(&index, &[("notes/x.md".into(), "notes/w.md".into())]);
+        assert_eq!(new_texts(&edits, "My Docs/z z.md"), ["../notes/w.md"]);
+        assert!(!edits.contains_key("notes/x.md"));
+    }
+
+    #[test]
+    fn link_edits_follow_notes_moved_together() {
+        let index = index(&[("a/x.md", "[Y](y.md)\n"), ("a/y.md", "[X](x.md)\n")]);
+        let edits = link_edits(
+            &index,
+            &[
+                ("a/x.md".into(), "b/x.md".into()),
+                ("a/y.md".into(), "b/y.md".into()),
+            ],
+        );
+        assert!(edits.is_empty());
+    }
+
+    #[test]
+    fn link_edits_retarget_links_through_aliases() {
+        let index = index(&[
+            ("real/note.md", "---\naliases: [My Alias]\n---\n"),
+            ("a.md", "[[My Alias]] and [[my alias|shown]]\n"),
+            ("b.md", "[[My Alias#Part]]\n"),
+        ]);
+        let edits = link_edits(&index, &[("real/note.md".into(), "archive/note.md".into())]);
+        assert_eq!(
+            new_texts(&edits, "a.md"),
+            ["archive/note|My Alias", "archive/note"]
+        );
+        assert_eq!(new_texts(&edits, "b.md"), ["archive/note"]);
+    }
+
+    #[test]
+    fn note_names_stay_inside_the_vault() {
+        assert_eq!(normalize_note_name(" ./a/b ").unwrap(), "a/b.md");
+        assert_eq!(normalize_note_name("/a/../c.md").unwrap(), "c.md");
+        assert!(normalize_note_name("../x").is_err());
+        assert!(normalize_note_name("a/../../x").is_err());
+    }
+
+    #[test]
+    fn note_names_must_fit_in_a_wiki_link() {
+        assert!(normalize_note_name("a#b").is_err());
+        assert!(normalize_note_name("a|b").is_err());
+        assert!(normalize_note_name("a]]b").is_err());
+    }
+}
@@ -0,0 +1,314 @@
+// src/handlers/semantic_tokens.rs
+use pulldown_cmark::{Event, Parser};
+use std::ops::Range;
+use tower_lsp::lsp_types::*;
+
+use crate::commands::CommandRegistry;
+use crate::document_store::{LineIndex, byte_to_utf16, utf16_to_byte};
+use crate::handlers::custom_commands::parse_command_syntax;
+use crate::handlers::formatting::markdown_options;
+use crate::handlers::frontmatter::{frontmatter_lines, line_key};
+use crate::outline::code_block_lines;
+use crate::vault_index::{NoteEntry, VaultIndex};
+use crate::wikilink::{find_wikilinks, split_anchor};
+
+// Indexes into the legend below.
+const WIKILINK_PATH: u32 = 0;
+const WIKILINK_TITLE: u32 = 1;
+const TAG: u32 = 2;
+const COMMAND: u32 = 3;
+const FRONTMATTER_KEY: u32 = 4;
+const MATH: u32 = 5;
+const FOOTNOTE: u32 = 6;
+
+/// Modifier bit set on links to notes that are not in the vault.
+const UNRESOLVED: u32 = 1 << 0;
+
+/// Token types and modifiers, using standard LSP names where one fits so that
+/// themes colour them without extra setup.
+pub fn legend() -> SemanticTokensLegend {
+    SemanticTokensLegend {
+        token_types: vec![
+            SemanticTokenType::NAMESPACE,
+            SemanticTokenType::STRING,
+            SemanticTokenType::DECORATOR,
+            SemanticTokenType::MACRO,
+            SemanticTokenType::PROPERTY,
+            SemanticTokenType::NUMBER,
+            SemanticTokenType::VARIABLE,
+        ],
+        token_modifiers: vec![SemanticTokenModifier::new("unresolved")],
+    }
+}
+
+/// A token as a byte range of the document, before encoding.
+struct Token {
+    span: Range<usize>,
+    token_type: u32,
+    modifiers: u32,
+}
+
+/// Classifies wiki-link paths and titles (flagging unresolved links), tags, `%%`
+/// commands known to `registry`, frontmatter keys, math and footnote references,
+/// encoded as LSP relative tokens.
+pub fn semantic_tokens(
+    text: &str,
+    index: &VaultIndex,
+    registry: &CommandRegistry,
+) -> Vec<SemanticToken> {
+    let mut tokens = Vec::new();
+    let lines: Vec<(usize, &str)> = text
+        .split_inclusive('\n')
+        .scan(0, |offset, line| {
+            let start = *offset;
+            *offset += line.len();
+            Some((start, line.trim_end_matches(['\n', '\r'])))
+        })
+        .collect();
+
+    // Frontmatter keys.
+    if let Some(block) = frontmatter_lines(text) {
+        for &(offset, line) in &lines[block] {
+            if let Some(key) = line_key(line) {
+                let start = offset + line.find(key).unwrap_or(0);
+                tokens.push(Token {
+                    span: start..start + key.len(),
+                    token_type: FRONTMATTER_KEY,
+                    modifiers: 0,
+                });
+            }
+        }
+    }
+
+    // Wiki-links and commands, outside code blocks.
+    let in_code = code_block_lines(text);
+    for (&(offset, line), in_code) in lines.iter().zip(in_code) {
+        if in_code {
+            continue;
+        }
+        // Only known commands: comments and unknown names are not commands.
+        if let Some(command) =
+            parse_command_syntax(line).filter(|command| registry.get(command.name).is_some())
+        {
+            tokens.push(Token {
+                span: offset + command.name_span.start..offset + command.name_span.end,
+                token_type: COMMAND,
+                modifiers: 0,
+            });
+        }
+        for link in find_wikilinks(line) {
+            let (path, _) = split_anchor(link.path);
+            let unresolved = !path.is_empty() && index.resolve(path).is_none();
+            tokens.push(Token {
+                span: offset + link.path_start..offset + link.path_end,
+                token_type: WIKILINK_PATH,
+                modifiers: if unresolved { UNRESOLVED } else { 0 },
+            });
+            if let Some(title) = link.title {
+                let title_start =
+                    link.path_end + line[link.path_end..link.end].find(title).unwrap_or(0);
+                tokens.push(Token {
+                    span: offset + title_start..offset + title_start + title.len(),
+                    token_type: WIKILINK_TITLE,
+                    modifiers: 0,
+                });
+            }
+        }
+    }
+
+    // Tags, from the same parse as the index.
+    let note = NoteEntry::parse("", text);
+    for tag in &note.tag_occurrences {
+        let (offset, line) = lines[tag.range.start.line as usize];
+        tokens.push(Token {
+            span: offset + utf16_to_byte(line, tag.range.start.character)
+                ..offset + utf16_to_byte(line, tag.range.end.character),
+            token_type: TAG,
+            modifiers: 0,
+        });
+    }
+
+    // Math and footnote references, from the formatter's markdown parse.
+    for (event, span) in Parser::new_ext(text, markdown_options()).into_offset_iter() {
+        let token_type = match event {
+            Event::InlineMath(_) | Event::DisplayMath(_) => MATH,
+            Event::FootnoteReference(_) => FOOTNOTE,
+            _ => continue,
+        };
+        tokens.push(Token {
+            span,
+            token_type,
+            modifiers: 0,
+        });
+    }
+
+    encode(text, tokens)
+}
+
+/// Sorts tokens, drops overlapping ones, splits multi-line tokens and encodes
+/// them relative to the previous token as LSP requires.
+fn encode(text: &str, mut tokens: Vec<Token>) -> Vec<SemanticToken> {
+    tokens.sort_by_key(|t| (t.span.start, t.span.end));
+    let line_index = LineIndex::new(text);
+    let mut encoded = Vec::new();
+    let (mut prev_line, mut prev_start, mut covered) = (0, 0, 0);
+
+    for token in tokens {
+        if token.span.start < covered || token.span.is_empty() {
+            continue;
+        }
+        covered = token.span.end;
+        let mut start = token.span.start;
+        for piece in text[token.span.clone()].split_inclusive('\n') {
+            let content = piece.trim_end_matches(['\n', '\r']);
+            let position = line_index.position(start);
+            start += piece.len();
+            if content.is_empty() {
+                continue;
+            }
+            let delta_line = position.line - prev_line;
+            let delta_start = if delta_line == 0 {
+                position.character - prev_start
+            } else {
+                position.character
+            };
+            encoded.push(SemanticToken {
+                delta_line,
+                delta_start,
+                length: byte_to_utf16(content, content.len()),
+                token_type: token.token_type,
+                token_modifiers_bitset: token.modifiers,
+            });
+            prev_line = position.line;
+            prev_start = position.character;
+        }
+    }
+    encoded
+}
+
+/// Edits turning `old` into `new`: a single replacement of everything between
+/// their common prefix and suffix. Offsets count integers, five per token.
+pub fn diff(old: &[SemanticToken], new: &[SemanticToken]) -> Vec<SemanticTokensEdit> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let deleted = old.len() - prefix - suffix;
+    let inserted = &new[prefix..new.len() - suffix];
+    if deleted == 0 && inserted.is_empty() {
+        return Vec::new();
+    }
+    vec![SemanticTokensEdit {
+        start: (prefix * 5) as u32,
+        delete_count: (deleted * 5) as u32,
+        data: Some(inserted.to_vec()),
+    }]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token(delta_line: u32, delta_start: u32) -> SemanticToken {
+        SemanticToken {
+            delta_line,
+            delta_start,
+            length: 1,
+            token_type: 0,
+            token_modifiers_bitset: 0,
+        }
+    }
+
+    #[test]
+    fn diff_of_equal_tokens_is_empty() {
+        let tokens = [token(0, 1), token(1, 2)];
+        assert!(diff(&tokens, &tokens).is_empty());
+        assert!(diff(&[], &[]).is_empty());
+    }
+
+    #[test]
+    fn diff_replaces_only_between_shared_prefix_and_suffix() {
+        let (a, b, c, x, y) = (
+            token(0, 1),
+            token(0, 2),
+            token(1, 0),
+            token(2, 0),
+            token(3, 0),
+        );
+        let edits = diff(&[a, b, c], &[a, x, y, c]);
+        assert_eq!(
+            edits,
+            [SemanticTokensEdit {
+                start: 5,
+                delete_count: 5,
+                data: Some(vec![x, y]),
+            }]
+        );
+    }
+
+    #[test]
+    fn diff_inserts_and_deletes_at_the_ends() {
+        let (a, b) = (token(0, 1), token(0, 2));
+        let edits = diff(&[a], &[a, b]);
+        assert_eq!(
+            edits,
+            [SemanticTokensEdit {
+                start: 5,
+                delete_count: 0,
+                data: Some(vec![b]),
+            }]
+        );
+        let edits = diff(&[a, b], &[b]);
+        assert_eq!(
+            edits,
+            [SemanticTokensEdit {
+                start: 0,
+                delete_count: 5,
+                data: Some(Vec::new()),
+            }]
+        );
+    }
+
+    #[test]
+    fn diff_does_not_count_a_token_in_both_prefix_and_suffix() {
+        let a = token(0, 1);
+        let edits = diff(&[a, a], &[a]);
+        assert_eq!(
+            edits,
+            [SemanticTokensEdit {
+                start: 5,
+                delete_count: 5,
+                data: Some(Vec::new()),
+            }]
+        );
+        let edits = diff(&[a], &[a, a, a]);
+        assert_eq!(
+            edits,
+            [SemanticTokensEdit {
+                start: 5,
+                delete_count: 0,
+                data: Some(vec![a, a]),
+            }]
+        );
+    }
+
+    #[test]
+    fn only_known_commands_are_tokens() {
+        let text = "%% comment\n%%nope x\n  %%nw ideas\n";
+        let registry = CommandRegistry::builtin();
+        let tokens = semantic_tokens(text, &VaultIndex::default(), &registry);
+        assert_eq!(
+            tokens,
+            [SemanticToken {
+                delta_line: 2,
+                delta_start: 2,
+                length: 4,
+                token_type: COMMAND,
+                token_modifiers_bitset: 0,
+            }]
+        );
+    }
+}
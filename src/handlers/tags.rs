@@ -0,0 +1,151 @@
+// src/handlers/tags.rs
+use std::collections::HashMap;
+use tower_lsp::lsp_types::*;
+
+use crate::document_store::{byte_to_utf16, utf16_to_byte};
+use crate::vault_index::{NoteEntry, TagOccurrence, VaultIndex, VaultSet, frontmatter_tag_lines};
+
+fn is_tag_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '/' || c == '-'
+}
+
+/// Completes tags after a `#` in the body and inside the frontmatter `tags` value.
+/// Candidates are every tag in the vault, most used first, with their note counts.
+pub fn tag_completions(
+    text: &str,
+    position: Position,
+    index: &VaultIndex,
+) -> Option<CompletionResponse> {
+    let line = text.lines().nth(position.line as usize)?;
+    let cursor = utf16_to_byte(line, position.character);
+    let before = &line[..cursor];
+
+    // The partial tag is the run of tag characters right before the cursor.
+    let start = before
+        .char_indices()
+        .rev()
+        .take_while(|(_, c)| is_tag_char(*c))
+        .last()
+        .map(|(i, _)| i)
+        .unwrap_or(cursor);
+    // A `#` at the start of the line begins a heading rather than a tag.
+    let after_hash = before[..start]
+        .strip_suffix('#')
+        .is_some_and(|rest| rest.ends_with(char::is_whitespace) && !rest.trim().is_empty());
+    let in_frontmatter_tags = frontmatter_tag_lines(text)
+        .iter()
+        .any(|&(l, value_start)| l == position.line as usize && start >= value_start);
+    if !after_hash && !in_frontmatter_tags {
+        return None;
+    }
+
+    let range = Range {
+        start: Position {
+            line: position.line,
+            character: byte_to_utf16(line, start),
+        },
+        end: position,
+    };
+    let mut tags: Vec<(&str, usize)> = index.tags().into_iter().collect();
+    tags.sort_by(|a, b| b.1.cmp(&a.1));
+    let items = tags
+        .into_iter()
+        .enumerate()
+        .map(|(rank, (tag, count))| CompletionItem {
+            label: tag.to_string(),
+            kind: Some(CompletionItemKind::KEYWORD),
+            detail: Some(note_count(count)),
+            sort_text: Some(format!("{:04}", rank)),
+            text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                range,
+                new_text: tag.to_string(),
+            })),
+            ..Default::default()
+        })
+        .collect();
+    Some(CompletionResponse::Array(items))
+}
+
+/// One workspace symbol per tag in the open vaults, pointing at its first use.
+pub fn tag_symbols(vaults: &VaultSet) -> Vec<SymbolInformation> {
+    let mut symbols = Vec::new();
+    for index in vaults.iter() {
+        let mut first_uses: HashMap<&str, (&NoteEntry, &TagOccurrence)> = HashMap::new();
+        for note in index.notes() {
+            for occurrence in &note.tag_occurrences {
+                first_uses
+                    .entry(occurrence.name.as_str())
+                    .or_insert((note, occurrence));
+            }
+        }
+        for (tag, count) in index.tags() {
+            let Some(&(note, occurrence)) = first_uses.get(tag) else {
+                continue;
+            };
+            let Some(uri) = index.note_uri(&note.path) else {
+                continue;
+            };
+            symbols.push(SymbolInformation {
+                name: format!("#{}", tag),
+                kind: SymbolKind::KEY,
+                location: Location {
+                    uri,
+                    range: occurrence.range,
+                },
+                container_name: Some(note_count(count)),
+                deprecated: None,
+                tags: None,
+            });
+        }
+    }
+    symbols
+}
+
+/// If the cursor is on a tag, returns every place in the vault that uses it. The
+/// current document is read from the buffer rather than the index.
+pub fn tag_references(
+    uri: &Url,
+    document_text: &str,
+    position: Position,
+    index: &VaultIndex,
+) -> Option<Vec<Location>> {
+    let current = NoteEntry::parse("", document_text);
+    let tag = current
+        .tag_occurrences
+        .iter()
+        .find(|t| t.range.start <= position && position <= t.range.end)?;
+    let current_path = uri
+        .to_file_path()
+        .ok()
+        .and_then(|path| index.relative_path(&path));
+
+    let mut locations: Vec<Location> = current
+        .tag_occurrences
+        .iter()
+        .filter(|t| t.name == tag.name)
+        .map(|t| Location {
+            uri: uri.clone(),
+            range: t.range,
+        })
+        .collect();
+    for (note, occurrence) in index.tag_occurrences(&tag.name) {
+        if Some(&note.path) == current_path.as_ref() {
+            continue;
+        }
+        if let Some(uri) = index.note_uri(&note.path) {
+            locations.push(Location {
+                uri,
+                range: occurrence.range,
+            });
+        }
+    }
+    Some(locations)
+}
+
+fn note_count(count: usize) -> String {
+    if count == 1 {
+        "1 note".to_string()
+    } else {
+        format!("{} notes", count)
+    }
+}
@@ -0,0 +1,111 @@
+// src/handlers/workspace_commands.rs
+use notemancy_core::workspaces::crud;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::path::Path;
+use tower_lsp::lsp_types::Url;
+
+use crate::commands::Mutation;
+
+pub const CREATE: &str = "notemancy.workspace.create";
+pub const APPEND: &str = "notemancy.workspace.append";
+pub const REMOVE: &str = "notemancy.workspace.remove";
+pub const LIST: &str = "notemancy.workspace.list";
+
+/// The commands advertised in `execute_command_provider`.
+pub fn commands() -> Vec<String> {
+    [CREATE, APPEND, REMOVE, LIST]
+        .iter()
+        .map(|c| c.to_string())
+        .collect()
+}
+
+/// Arguments of the workspace commands, passed as a single object:
+/// `{ "workspace": "name", "uri": "file:///vault/note.md" }`. `uri` is the note to
+/// add or remove; for `list` it only selects the vault. The `%%` command requests
+/// (`notemancy.previewCommands`, `notemancy.runCommands`) take just the `uri`, and
+/// `notemancy.runCommands` an optional `line` to run only the command on it.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct WorkspaceArgs {
+    pub workspace: Option<String>,
+    pub uri: Option<Url>,
+    pub line: Option<u32>,
+}
+
+impl WorkspaceArgs {
+    pub fn parse(arguments: &[Value]) -> Result<Self, String> {
+        match arguments.first() {
+            Some(value) => serde_json::from_value(value.clone())
+                .map_err(|e| format!("Invalid command arguments: {}", e)),
+            None => Ok(Self::default()),
+        }
+    }
+}
+
+/// Names of the workspaces in a vault.
+pub fn list_workspaces(vault_dir: &Path) -> Result<Vec<String>, String> {
+    crud::list_workspaces(vault_dir).map_err(|e| format!("Failed to list workspaces: {}", e))
+}
+
+/// A workspace of a vault and the notes in it.
+#[derive(Debug, Clone)]
+pub struct Workspace {
+    pub name: String,
+    /// Paths of the notes, as they were added.
+    pub notes: Vec<String>,
+}
+
+impl Workspace {
+    pub fn contains(&self, note: &Path) -> bool {
+        self.notes.iter().any(|n| Path::new(n) == note)
+    }
+}
+
+/// The workspaces of a vault with their notes.
+pub fn list_workspace_contents(vault_dir: &Path) -> Result<Vec<Workspace>, String> {
+    list_workspaces(vault_dir)?
+        .into_iter()
+        .map(|name| {
+            let notes = crud::list_workspace_notes(vault_dir, &name)
+                .map_err(|e| format!("Failed to read workspace '{}': {}", name, e))?;
+            Ok(Workspace { name, notes })
+        })
+        .collect()
+}
+
+/// The vault mutation a workspace command makes to the note in `args`, along
+/// with the command's result as JSON. `list` makes none and is not accepted here.
+pub fn mutation(command: &str, args: &WorkspaceArgs) -> Result<(Mutation, Value), String> {
+    let workspace = args
+        .workspace
+        .clone()
+        .filter(|w| !w.is_empty())
+        .ok_or("Missing workspace name")?;
+    let uri = args.uri.as_ref().ok_or("Missing note URI")?;
+    let note = uri
+        .to_file_path()
+        .map_err(|_| "Invalid file URI".to_string())?;
+    let note_str = note
+        .to_str()
+        .ok_or("Failed to convert file path to string")?
+        .to_string();
+
+    let value =
+        |action: &str| json!({ "workspace": workspace, "note": note_str, "action": action });
+    let (value, mutation) = match command {
+        CREATE => (
+            value("created"),
+            Mutation::CreateWorkspace { workspace, note },
+        ),
+        APPEND => (
+            value("appended"),
+            Mutation::AppendToWorkspace { workspace, note },
+        ),
+        REMOVE => (
+            value("removed"),
+            Mutation::RemoveFromWorkspace { workspace, note },
+        ),
+        _ => return Err(format!("Unknown command: {}", command)),
+    };
+    Ok((mutation, value))
+}
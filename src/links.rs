@@ -0,0 +1,208 @@
+// src/links.rs
+use pulldown_cmark::{Event, LinkType, Parser, Tag, TagEnd};
+use regex::Regex;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+use tower_lsp::lsp_types::{Position, Url};
+
+use crate::document_store::utf16_to_byte;
+use crate::handlers::formatting::markdown_options;
+use crate::vault_index::{VaultIndex, normalize_relative, percent_decode};
+use crate::wikilink::split_anchor;
+
+static BARE_URL_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"https?://[^\s<>()\[\]"'`]+[^\s<>()\[\]"'`.,;:!?]"#).unwrap());
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DocLinkKind {
+    /// `[[path | title]]`
+    Wiki,
+    /// `[text](path.md)`, `[text][ref]` and `<https://…>`
+    Markdown,
+    /// `![alt](assets/x.png)`
+    Image,
+    /// A URL written as plain text.
+    BareUrl,
+}
+
+/// A link found in a document. Offsets are byte offsets into the document.
+#[derive(Debug, Clone)]
+pub struct DocLink {
+    pub kind: DocLinkKind,
+    /// The destination, with reference-style links already resolved.
+    pub dest: String,
+    /// The whole link.
+    pub span: Range<usize>,
+    /// Where the destination is written, if it is written inside the link.
+    pub dest_span: Option<Range<usize>>,
+}
+
+/// Where a link points.
+#[derive(Debug, Clone)]
+pub enum LinkTarget {
+    /// A note in the vault, by vault-relative path, with an optional heading or
+    /// `^block-id` anchor.
+    Note {
+        path: String,
+        anchor: Option<String>,
+    },
+    /// Any other local file.
+    File(PathBuf),
+    Url(Url),
+}
+
+/// Finds every wiki-link, markdown link (inline, reference-style or autolink),
+/// image and bare URL in the document, using the formatter's markdown parse.
+pub fn find_links(text: &str) -> Vec<DocLink> {
+    let mut links = Vec::new();
+    // Nesting depth of the links and images being parsed; bare URLs inside them
+    // are part of the link.
+    let mut depth = 0;
+    let mut in_code_block = false;
+
+    for (event, span) in Parser::new_ext(text, markdown_options()).into_offset_iter() {
+        match event {
+            Event::Start(Tag::Link {
+                link_type,
+                dest_url,
+                ..
+            }) => {
+                depth += 1;
+                let kind = match link_type {
+                    LinkType::WikiLink { .. } => DocLinkKind::Wiki,
+                    _ => DocLinkKind::Markdown,
+                };
+                links.push(doc_link(text, kind, link_type, &dest_url, span));
+            }
+            Event::Start(Tag::Image {
+                link_type,
+                dest_url,
+                ..
+            }) => {
+                depth += 1;
+                links.push(doc_link(
+                    text,
+                    DocLinkKind::Image,
+                    link_type,
+                    &dest_url,
+                    span,
+                ));
+            }
+            Event::End(TagEnd::Link) | Event::End(TagEnd::Image) => depth -= 1,
+            Event::Start(Tag::CodeBlock(_)) => in_code_block = true,
+            Event::End(TagEnd::CodeBlock) => in_code_block = false,
+            // Search the source rather than the event text, which smart punctuation may alter.
+            Event::Text(_) if depth == 0 && !in_code_block => {
+                for m in BARE_URL_RE.find_iter(&text[span.clone()]) {
+                    let url_span = span.start + m.start()..span.start + m.end();
+                    links.push(DocLink {
+                        kind: DocLinkKind::BareUrl,
+                        dest: m.as_str().to_string(),
+                        span: url_span.clone(),
+                        dest_span: Some(url_span),
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    links
+}
+
+/// Returns the link under the cursor, if any.
+pub fn link_at(text: &str, position: Position) -> Option<DocLink> {
+    let line_start: usize = text
+        .split_inclusive('\n')
+        .take(position.line as usize)
+        .map(str::len)
+        .sum();
+    let line = text[line_start..].lines().next().unwrap_or("");
+    let offset = line_start + utf16_to_byte(line, position.character);
+    find_links(text)
+        .into_iter()
+        .find(|link| link.span.start <= offset && offset <= link.span.end)
+}
+
+fn doc_link(
+    text: &str,
+    kind: DocLinkKind,
+    link_type: LinkType,
+    dest: &str,
+    span: Range<usize>,
+) -> DocLink {
+    let dest = dest.trim();
+    let source = &text[span.clone()];
+    let dest_offset = match link_type {
+        LinkType::WikiLink { .. } => source.find(dest),
+        LinkType::Inline | LinkType::Autolink | LinkType::Email => source.rfind(dest),
+        // The destination of reference-style links is in the definition.
+        _ => None,
+    };
+    DocLink {
+        kind,
+        dest: dest.to_string(),
+        dest_span: dest_offset
+            .filter(|_| !dest.is_empty())
+            .map(|offset| span.start + offset..span.start + offset + dest.len()),
+        span,
+    }
+}
+
+/// Resolves a link in the document at `uri`. Wiki-links are looked up in the vault
+/// (by path or alias); other relative links are percent-decoded and resolved
+/// against the document's directory, or the vault root if they start with `/`.
+pub fn resolve_link(link: &DocLink, uri: &Url, index: &VaultIndex) -> Option<LinkTarget> {
+    let current_path = uri.to_file_path().ok();
+    let current = current_path
+        .as_deref()
+        .and_then(|path| index.relative_path(path));
+
+    if link.kind == DocLinkKind::BareUrl
+        || link.dest.contains("://")
+        || link.dest.starts_with("mailto:")
+    {
+        return Url::parse(&link.dest).ok().map(LinkTarget::Url);
+    }
+
+    let (dest_path, anchor) = split_anchor(&link.dest);
+    let anchor = anchor.filter(|a| !a.is_empty()).map(str::to_string);
+    if dest_path.is_empty() {
+        // `[[#Heading]]` and `[text](#heading)` point into the document itself.
+        return Some(LinkTarget::Note {
+            path: current?,
+            anchor,
+        });
+    }
+
+    if link.kind == DocLinkKind::Wiki {
+        let note = index.resolve(dest_path)?;
+        return Some(LinkTarget::Note {
+            path: note.path.clone(),
+            anchor,
+        });
+    }
+
+    // Markdown links and images are relative to the document (or the vault root).
+    let dest_path = percent_decode(dest_path);
+    let dest_path = dest_path.as_str();
+    let in_vault = match (&current, dest_path.strip_prefix('/')) {
+        (_, Some(rooted)) => normalize_relative(Path::new(rooted)),
+        (Some(current), None) => {
+            let base = Path::new(current).parent().unwrap_or(Path::new(""));
+            normalize_relative(&base.join(dest_path))
+        }
+        (None, None) => None,
+    };
+    if let Some(note) = in_vault.as_deref().and_then(|path| index.get(path)) {
+        return Some(LinkTarget::Note {
+            path: note.path.clone(),
+            anchor,
+        });
+    }
+    let file = match in_vault {
+        Some(path) => index.root().join(path),
+        None => current_path?.parent()?.join(dest_path),
+    };
+    file.exists().then_some(LinkTarget::File(file))
+}
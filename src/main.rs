@@ -1,5 +1,15 @@
+mod commands;
+mod config;
+mod document_store;
+mod fuzzy;
 mod handlers;
+mod links;
+mod outline;
 mod server;
+mod settings;
+mod vault_index;
+mod watcher;
+mod wikilink;
 
 use server::NotemancyServer;
 use tower_lsp::{LspService, Server};
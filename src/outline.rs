@@ -0,0 +1,178 @@
+// src/outline.rs
+use pulldown_cmark::{CodeBlockKind, Event, MetadataBlockKind, Parser, Tag, TagEnd};
+use std::ops::Range;
+
+use crate::handlers::formatting::markdown_options;
+
+/// A block-level element of a markdown document found by the same pulldown-cmark
+/// parse the formatter uses.
+#[derive(Debug, Clone)]
+pub struct Block {
+    pub kind: BlockKind,
+    /// Byte range of the block in the document.
+    pub span: Range<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub enum BlockKind {
+    Heading {
+        level: usize,
+        text: String,
+    },
+    Frontmatter,
+    CodeBlock {
+        language: Option<String>,
+    },
+    Table,
+    FootnoteDefinition {
+        label: String,
+    },
+    ListItem,
+    /// A block quote, including `> [!note]` callouts.
+    BlockQuote,
+    HtmlComment,
+}
+
+/// Returns the document's blocks in document order.
+pub fn parse_blocks(text: &str) -> Vec<Block> {
+    let mut blocks = Vec::new();
+    // Heading being parsed: (index in `blocks`, accumulated text).
+    let mut heading: Option<(usize, String)> = None;
+
+    for (event, span) in Parser::new_ext(text, markdown_options()).into_offset_iter() {
+        match event {
+            Event::Start(Tag::Heading { level, .. }) => {
+                heading = Some((blocks.len(), String::new()));
+                blocks.push(Block {
+                    kind: BlockKind::Heading {
+                        level: level as usize,
+                        text: String::new(),
+                    },
+                    span,
+                });
+            }
+            Event::End(TagEnd::Heading(_)) => {
+                if let Some((i, heading_text)) = heading.take() {
+                    if let BlockKind::Heading { text, .. } = &mut blocks[i].kind {
+                        *text = heading_text.trim().to_string();
+                    }
+                }
+            }
+            Event::Text(t) | Event::Code(t) | Event::InlineMath(t) => {
+                if let Some((_, heading_text)) = heading.as_mut() {
+                    heading_text.push_str(&t);
+                }
+            }
+            Event::Start(Tag::MetadataBlock(MetadataBlockKind::YamlStyle)) => {
+                blocks.push(Block {
+                    kind: BlockKind::Frontmatter,
+                    span,
+                });
+            }
+            Event::Start(Tag::CodeBlock(kind)) => {
+                let language = match kind {
+                    CodeBlockKind::Fenced(info) => {
+                        info.split_whitespace().next().map(str::to_string)
+                    }
+                    CodeBlockKind::Indented => None,
+                };
+                blocks.push(Block {
+                    kind: BlockKind::CodeBlock { language },
+                    span,
+                });
+            }
+            Event::Start(Tag::Table(_)) => {
+                blocks.push(Block {
+                    kind: BlockKind::Table,
+                    span,
+                });
+            }
+            Event::Start(Tag::FootnoteDefinition(label)) => {
+                blocks.push(Block {
+                    kind: BlockKind::FootnoteDefinition {
+                        label: label.to_string(),
+                    },
+                    span,
+                });
+            }
+            Event::Start(Tag::Item) => {
+                blocks.push(Block {
+                    kind: BlockKind::ListItem,
+                    span,
+                });
+            }
+            Event::Start(Tag::BlockQuote(_)) => {
+                blocks.push(Block {
+                    kind: BlockKind::BlockQuote,
+                    span,
+                });
+            }
+            Event::Start(Tag::HtmlBlock) if text[span.clone()].trim_start().starts_with("<!--") => {
+                blocks.push(Block {
+                    kind: BlockKind::HtmlComment,
+                    span,
+                });
+            }
+            _ => {}
+        }
+    }
+    blocks
+}
+
+/// End of the section opened by the heading `blocks[i]`: the start of the next
+/// heading of the same or a higher level, or the end of the document.
+pub fn section_end(text: &str, blocks: &[Block], i: usize) -> usize {
+    let BlockKind::Heading { level, .. } = blocks[i].kind else {
+        return blocks[i].span.end;
+    };
+    blocks[i + 1..]
+        .iter()
+        .find_map(|b| match b.kind {
+            BlockKind::Heading { level: l, .. } if l <= level => Some(b.span.start),
+            _ => None,
+        })
+        .unwrap_or(text.len())
+}
+
+/// Whether each line of the text (as split by `str::lines`) belongs to a code
+/// block, fences included. Fenced blocks may use backticks or tildes of any
+/// length, and indented code blocks count too.
+pub fn code_block_lines(text: &str) -> Vec<bool> {
+    let line_starts: Vec<usize> = text
+        .split_inclusive('\n')
+        .scan(0, |offset, line| {
+            let start = *offset;
+            *offset += line.len();
+            Some(start)
+        })
+        .collect();
+    let line_of = |offset: usize| line_starts.partition_point(|&start| start <= offset) - 1;
+    let mut in_code = vec![false; line_starts.len()];
+    for block in parse_blocks(text) {
+        if !matches!(block.kind, BlockKind::CodeBlock { .. }) {
+            continue;
+        }
+        let span = trim_end(text, block.span);
+        if span.is_empty() {
+            continue;
+        }
+        for line in &mut in_code[line_of(span.start)..=line_of(span.end - 1)] {
+            *line = true;
+        }
+    }
+    in_code
+}
+
+/// Byte ranges of the inline code spans of the text, backticks included.
+pub fn code_spans(text: &str) -> Vec<Range<usize>> {
+    Parser::new_ext(text, markdown_options())
+        .into_offset_iter()
+        .filter(|(event, _)| matches!(event, Event::Code(_)))
+        .map(|(_, span)| span)
+        .collect()
+}
+
+/// Drops trailing whitespace from a span so ranges end on the last line of content.
+pub fn trim_end(text: &str, span: Range<usize>) -> Range<usize> {
+    span.start..span.start + text[span].trim_end().len()
+}
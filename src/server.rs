@@ -1,37 +1,1031 @@
 // src/server.rs
 use async_trait::async_trait;
 use lsp_types::*;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use std::time::Duration;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
 use tokio::sync::RwLock;
 use tower_lsp::{Client, LanguageServer};
 
+use crate::commands::{self, CommandContext, CommandRegistry, Mutation};
+use crate::config::{self, ConfigFile, Vault};
+use crate::document_store::{Document, DocumentStore};
+use crate::handlers::code_actions;
 use crate::handlers::completion; // existing modules
-use crate::handlers::custom_commands;
+use crate::handlers::custom_commands::{self, ProcessedCommands};
+use crate::handlers::diagnostics;
+use crate::handlers::document_links;
 use crate::handlers::document_symbols::document_symbols;
+use crate::handlers::folding;
 use crate::handlers::formatting;
-use crate::handlers::goto::goto_wikilink;
+use crate::handlers::frontmatter;
+use crate::handlers::goto::{goto_markdown_link, goto_wikilink};
 use crate::handlers::hover_wikilink;
+use crate::handlers::references;
+use crate::handlers::rename;
+use crate::handlers::semantic_tokens;
+use crate::handlers::tags;
+use crate::handlers::workspace_commands::{self, Workspace, WorkspaceArgs};
 use crate::handlers::workspace_symbols; // new formatting handler
+use crate::settings::Settings;
+use crate::vault_index::{DiskNote, VaultIndex, VaultSet};
+use crate::watcher::{self, WatchEvent};
 
+/// Last semantic tokens sent per document, with their result ID.
+type SemanticTokenCache = HashMap<Url, (String, Vec<SemanticToken>)>;
+/// Vault mutations made by `%%` and workspace commands with their vault, newest last.
+type CommandHistory = Vec<(PathBuf, Vec<Mutation>)>;
+/// Workspaces per vault directory, with when they were listed.
+type WorkspaceCache = HashMap<PathBuf, (Instant, Vec<Workspace>)>;
+
+/// How long listed workspaces are reused. Changes made through the server are
+/// seen at once; this bounds how stale changes made by other tools can be.
+const WORKSPACE_CACHE_TTL: Duration = Duration::from_secs(10);
+
+/// The server state. Every field is shared, so clones are handles to the same
+/// server, e.g. for background tasks.
+#[derive(Clone)]
 pub struct NotemancyServer {
     client: Client,
-    // Store open document texts by their URI – works for unsaved buffers too.
-    documents: Arc<RwLock<HashMap<Url, String>>>,
+    // Store open documents by their URI – works for unsaved buffers too.
+    // When both are needed, lock `vaults` before `documents`.
+    documents: Arc<RwLock<DocumentStore>>,
+    // Indexes of the vaults open in this session, each built when first needed.
+    vaults: Arc<RwLock<VaultSet>>,
+    // Names of the vaults being built with the generation they were started in, so
+    // concurrent opens build each vault once.
+    loading_vaults: Arc<std::sync::Mutex<HashSet<(u64, String)>>>,
+    // Bumped by every reopen of the vaults; builds started before it are discarded.
+    vault_generation: Arc<AtomicU64>,
+    // Settings from `initializationOptions` and `workspace/didChangeConfiguration`.
+    settings: Arc<RwLock<Settings>>,
+    // config.yaml as last loaded; replaced when the file changes.
+    config: Arc<RwLock<Option<ConfigFile>>>,
+    // Workspace folders reported by the client, used to pick the vaults to open.
+    workspace_folders: Arc<RwLock<Vec<PathBuf>>>,
+    // Whether the client can register `workspace/didChangeWatchedFiles` for us.
+    client_watches_files: Arc<AtomicBool>,
+    // Fallback watchers (one per open vault) used when the client cannot watch files itself.
+    fs_watchers: Arc<std::sync::Mutex<Vec<notify::RecommendedWatcher>>>,
+    // Last semantic tokens sent per document, with their result ID, for delta requests.
+    semantic_tokens: Arc<RwLock<SemanticTokenCache>>,
+    next_result_id: Arc<AtomicU64>,
+    // Fallback watcher on config.yaml, for the same clients.
+    config_watcher: Arc<std::sync::Mutex<Option<notify::RecommendedWatcher>>>,
+    // Vault mutations made by `%%` and workspace commands with their vault, newest last, for undo.
+    command_history: Arc<std::sync::Mutex<CommandHistory>>,
+    // Workspaces listed for code actions and `%%` commands.
+    workspace_cache: Arc<RwLock<WorkspaceCache>>,
+}
+
+/// What the `%%` commands of a document need: the registry including config.yaml's
+/// commands, the note and its vault, and the vault's workspaces if they were listed.
+struct CommandEnv {
+    registry: CommandRegistry,
+    vault_dir: PathBuf,
+    note: PathBuf,
+    workspaces: Option<Vec<String>>,
+}
+
+impl CommandEnv {
+    fn context(&self) -> CommandContext<'_> {
+        CommandContext {
+            vault_dir: &self.vault_dir,
+            note: &self.note,
+            workspaces: self.workspaces.as_deref(),
+        }
+    }
 }
 
 impl NotemancyServer {
     pub fn new(client: Client) -> Self {
         Self {
             client,
-            documents: Arc::new(RwLock::new(HashMap::new())),
+            documents: Arc::new(RwLock::new(DocumentStore::new())),
+            vaults: Arc::new(RwLock::new(VaultSet::default())),
+            loading_vaults: Arc::new(std::sync::Mutex::new(HashSet::new())),
+            vault_generation: Arc::new(AtomicU64::new(0)),
+            settings: Arc::new(RwLock::new(Settings::default())),
+            config: Arc::new(RwLock::new(None)),
+            workspace_folders: Arc::new(RwLock::new(Vec::new())),
+            client_watches_files: Arc::new(AtomicBool::new(false)),
+            fs_watchers: Arc::new(std::sync::Mutex::new(Vec::new())),
+            semantic_tokens: Arc::new(RwLock::new(HashMap::new())),
+            next_result_id: Arc::new(AtomicU64::new(1)),
+            config_watcher: Arc::new(std::sync::Mutex::new(None)),
+            command_history: Arc::new(std::sync::Mutex::new(Vec::new())),
+            workspace_cache: Arc::new(RwLock::new(HashMap::new())),
         }
     }
 
     async fn get_document_text(&self, uri: &Url) -> Option<String> {
         let docs = self.documents.read().await;
-        docs.get(uri).cloned()
+        docs.text(uri)
+    }
+
+    /// The vaults relevant to this session and the name of the fallback vault:
+    /// every vault overlapping a workspace folder plus the default vault. Documents
+    /// outside all of them fall back to the vault of the first workspace folder, or
+    /// the default vault. A `vaultPath` setting replaces all of this with that
+    /// single vault.
+    async fn initial_vaults(&self) -> (Option<String>, Vec<Vault>) {
+        if let Some(vault_path) = self.settings.read().await.vault_path.clone() {
+            let name = vault_path
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_else(|| vault_path.display().to_string());
+            let vault = Vault {
+                name: name.clone(),
+                vault_directory: vault_path.display().to_string(),
+                publish_url: None,
+                // Keep the schema of a configured vault in the same directory.
+                frontmatter: self
+                    .config
+                    .read()
+                    .await
+                    .as_ref()
+                    .and_then(|c| {
+                        c.vaults
+                            .iter()
+                            .find(|v| Path::new(&v.vault_directory) == vault_path)
+                    })
+                    .map(|v| v.frontmatter.clone())
+                    .unwrap_or_default(),
+            };
+            return (Some(name), vec![vault]);
+        }
+
+        let Some(config) = self.config.read().await.clone() else {
+            return (None, Vec::new());
+        };
+
+        let folders = self.workspace_folders.read().await.clone();
+        let mut to_open: Vec<Vault> = Vec::new();
+        for folder in &folders {
+            for vault in config.vaults_for_folder(folder) {
+                if !to_open.iter().any(|v| v.name == vault.name) {
+                    to_open.push(vault.clone());
+                }
+            }
+        }
+        let fallback = folders
+            .iter()
+            .find_map(|folder| config.vault_for_path(folder))
+            .map(|v| v.name.clone());
+        match config.default_vault() {
+            Ok(default) => {
+                if !to_open.iter().any(|v| v.name == default.name) {
+                    to_open.push(default.clone());
+                }
+            }
+            Err(e) => {
+                self.client.log_message(MessageType::WARNING, e).await;
+            }
+        }
+        let fallback = fallback.unwrap_or_else(|| config.default_vault.clone());
+        (Some(fallback), to_open)
+    }
+
+    /// Opens the vaults relevant to this session, see `initial_vaults`.
+    async fn open_initial_vaults(&self) {
+        let (fallback, to_open) = self.initial_vaults().await;
+        if let Some(fallback) = fallback {
+            self.vaults.write().await.set_fallback(fallback);
+        }
+        for vault in to_open {
+            self.open_vault(vault).await;
+        }
+    }
+
+    /// Opens the vault containing a document if it is configured but not open yet.
+    async fn ensure_vault_for(&self, uri: &Url) {
+        let Ok(path) = uri.to_file_path() else {
+            return;
+        };
+        if self.settings.read().await.vault_path.is_some() {
+            return;
+        }
+        let config = self.config.read().await;
+        if let Some(vault) = config
+            .as_ref()
+            .and_then(|c| c.vault_for_path(&path))
+            .cloned()
+        {
+            drop(config);
+            self.open_vault(vault).await;
+        }
+    }
+
+    /// Builds a vault index from disk without blocking the async runtime. Does
+    /// nothing if the vault is already open or being built. Open documents of the
+    /// vault are re-applied to the new index so it includes their unsaved edits.
+    async fn open_vault(&self, vault: Vault) {
+        let name = vault.name.clone();
+        let generation = self.vault_generation.load(Ordering::SeqCst);
+        let loading = (generation, name.clone());
+        if self.vaults.read().await.contains(&name)
+            || !self.loading_vaults.lock().unwrap().insert(loading.clone())
+        {
+            return;
+        }
+        let index = self.build_vault(vault).await;
+        let root = {
+            // Lock order: vaults, then documents.
+            let mut vaults = self.vaults.write().await;
+            self.loading_vaults.lock().unwrap().remove(&loading);
+            let Some(mut index) = index else {
+                return;
+            };
+            // Vaults reopened while this one was building: it may come from an old config.
+            if generation != self.vault_generation.load(Ordering::SeqCst) || vaults.contains(&name)
+            {
+                return;
+            }
+            let docs = self.documents.read().await;
+            for uri in docs.uris() {
+                let Ok(path) = uri.to_file_path() else {
+                    continue;
+                };
+                if let (Some(relative), Some(text)) = (index.relative_path(&path), docs.text(&uri))
+                {
+                    index.update_note(&relative, &text);
+                }
+            }
+            let root = index.root().to_path_buf();
+            vaults.insert(index);
+            root
+        };
+        self.watch_vault(&root).await;
+    }
+
+    /// Reads and parses a vault on the blocking pool, logging the outcome.
+    async fn build_vault(&self, vault: Vault) -> Option<VaultIndex> {
+        let name = vault.name.clone();
+        let root = PathBuf::from(&vault.vault_directory);
+        let schema = vault.frontmatter.clone();
+        let result = tokio::task::spawn_blocking(move || VaultIndex::build(vault.name, root)).await;
+        match result {
+            Ok(Ok(mut index)) => {
+                index.set_schema(schema);
+                self.client
+                    .log_message(
+                        MessageType::INFO,
+                        format!(
+                            "Indexed {} notes in vault '{}'",
+                            index.notes().count(),
+                            name
+                        ),
+                    )
+                    .await;
+                Some(index)
+            }
+            Ok(Err(e)) => {
+                self.client
+                    .log_message(
+                        MessageType::ERROR,
+                        format!("Failed to index vault '{}': {}", name, e),
+                    )
+                    .await;
+                None
+            }
+            Err(e) => {
+                self.client
+                    .log_message(
+                        MessageType::ERROR,
+                        format!("Vault indexing panicked: {}", e),
+                    )
+                    .await;
+                None
+            }
+        }
+    }
+
+    /// Recomputes and publishes wiki-link diagnostics for an open document.
+    async fn publish_diagnostics(&self, uri: Url) {
+        let (text, version) = {
+            let docs = self.documents.read().await;
+            match docs.get(&uri) {
+                Some(doc) => (doc.text(), doc.version()),
+                None => return,
+            }
+        };
+        // Workspaces are only listed when there are commands to check.
+        let commands = if text.contains("%%") {
+            self.command_env(&uri, true).await.ok()
+        } else {
+            None
+        };
+        let settings = self.settings.read().await;
+        let vaults = self.vaults.read().await;
+        let Some(index) = vaults.for_uri(&uri) else {
+            return;
+        };
+        let diagnostics = if settings.features.diagnostics {
+            let mut diagnostics =
+                diagnostics::wikilink_diagnostics(&text, index, &settings.diagnostics);
+            diagnostics.extend(diagnostics::frontmatter_diagnostics(
+                &text,
+                index.schema(),
+                &settings.diagnostics,
+            ));
+            if let Some(env) = &commands {
+                diagnostics.extend(diagnostics::command_diagnostics(
+                    &text,
+                    &env.registry,
+                    Some(&env.context()),
+                    &settings.diagnostics,
+                ));
+            }
+            diagnostics
+        } else {
+            Vec::new()
+        };
+        drop(vaults);
+        drop(settings);
+        self.client
+            .publish_diagnostics(uri, diagnostics, Some(version))
+            .await;
+    }
+
+    /// Asks the client to send `workspace/didChangeWatchedFiles` for notes and for
+    /// directories, whose notes come and go with them. If the client cannot, every
+    /// vault gets an internal watcher instead.
+    async fn register_file_watcher(&self) {
+        if !self.client_watches_files.load(Ordering::Relaxed) {
+            return;
+        }
+        let mut watchers = vec![
+            FileSystemWatcher {
+                glob_pattern: "**/*.md".to_string().into(),
+                kind: None,
+            },
+            // Editors only report a directory for a pattern matching it; creates and
+            // deletes are enough, as a rename is reported as both.
+            FileSystemWatcher {
+                glob_pattern: "**/*".to_string().into(),
+                kind: Some(WatchKind::Create | WatchKind::Delete),
+            },
+        ];
+        if let Ok(path) = config::config_path() {
+            watchers.push(FileSystemWatcher {
+                glob_pattern: path.display().to_string().into(),
+                kind: None,
+            });
+        }
+        let options = DidChangeWatchedFilesRegistrationOptions { watchers };
+        let registration = Registration {
+            id: "notemancy-watch-notes".to_string(),
+            method: "workspace/didChangeWatchedFiles".to_string(),
+            register_options: serde_json::to_value(options).ok(),
+        };
+        if let Err(e) = self.client.register_capability(vec![registration]).await {
+            self.client_watches_files.store(false, Ordering::Relaxed);
+            self.client
+                .log_message(
+                    MessageType::WARNING,
+                    format!("Failed to register file watcher: {}", e),
+                )
+                .await;
+        }
+    }
+
+    /// Starts an internal watcher on a vault directory unless the client watches files for us.
+    async fn watch_vault(&self, root: &Path) {
+        if self.client_watches_files.load(Ordering::Relaxed) {
+            return;
+        }
+        match watcher::spawn_fs_watcher(root, self.vaults.clone(), self.documents.clone()) {
+            Ok((w, mut rx)) => {
+                self.fs_watchers.lock().unwrap().push(w);
+                // Links in open documents may have started or stopped resolving.
+                let server = self.clone();
+                tokio::spawn(async move {
+                    while rx.recv().await.is_some() {
+                        server.publish_all_diagnostics().await;
+                    }
+                });
+            }
+            Err(e) => {
+                self.client
+                    .log_message(
+                        MessageType::ERROR,
+                        format!("Failed to watch {}: {}", root.display(), e),
+                    )
+                    .await;
+            }
+        }
+    }
+
+    /// Directory of the vault a document belongs to, for commands that write to the
+    /// vault. Without a document, the fallback vault is used.
+    async fn vault_dir(&self, uri: Option<&Url>) -> Result<PathBuf, String> {
+        match uri {
+            Some(uri) => self.vault_dir_for(uri).await,
+            None => self
+                .vaults
+                .read()
+                .await
+                .fallback()
+                .map(|index| index.root().to_path_buf())
+                .ok_or_else(|| "No vault configured".to_string()),
+        }
+    }
+
+    /// Directory of the vault a document belongs to, for commands that write to the vault.
+    async fn vault_dir_for(&self, uri: &Url) -> Result<PathBuf, String> {
+        if let Some(index) = self.vaults.read().await.for_uri(uri) {
+            return Ok(index.root().to_path_buf());
+        }
+        let path = uri
+            .to_file_path()
+            .map_err(|_| "Invalid file URI".to_string())?;
+        match self.config.read().await.as_ref() {
+            Some(config) => config.vault_directory_for(&path),
+            None => Err("No vault configured".to_string()),
+        }
+    }
+
+    /// Workspaces of the vault a document belongs to, with their notes. Listings
+    /// are cached per vault for `WORKSPACE_CACHE_TTL`.
+    async fn workspaces_for(&self, uri: &Url) -> Result<Vec<Workspace>, String> {
+        let vault_dir = self.vault_dir_for(uri).await?;
+        let cached = self
+            .workspace_cache
+            .read()
+            .await
+            .get(&vault_dir)
+            .filter(|(listed, _)| listed.elapsed() < WORKSPACE_CACHE_TTL)
+            .map(|(_, workspaces)| workspaces.clone());
+        if let Some(workspaces) = cached {
+            return Ok(workspaces);
+        }
+        // Reads every workspace file, so off the async runtime: diagnostics list them
+        // as the user types.
+        let dir = vault_dir.clone();
+        let workspaces =
+            tokio::task::spawn_blocking(move || workspace_commands::list_workspace_contents(&dir))
+                .await
+                .unwrap_or_else(|e| Err(format!("Listing workspaces panicked: {}", e)))?;
+        self.workspace_cache
+            .write()
+            .await
+            .insert(vault_dir, (Instant::now(), workspaces.clone()));
+        Ok(workspaces)
+    }
+
+    /// Drops the cached workspaces after the server changed some.
+    async fn forget_workspaces(&self) {
+        self.workspace_cache.write().await.clear();
+    }
+
+    /// The built-in `%%` commands and those defined in config.yaml.
+    async fn command_registry(&self) -> CommandRegistry {
+        CommandRegistry::with_config(self.config.read().await.as_ref())
+    }
+
+    /// Everything the `%%` commands of a document need. Listing the workspaces is
+    /// only worth it for validation and completion.
+    async fn command_env(&self, uri: &Url, list_workspaces: bool) -> Result<CommandEnv, String> {
+        let vault_dir = self.vault_dir_for(uri).await?;
+        let note = uri
+            .to_file_path()
+            .map_err(|_| "Invalid file URI".to_string())?;
+        let workspaces = if list_workspaces {
+            self.workspaces_for(uri)
+                .await
+                .ok()
+                .map(|workspaces| workspaces.into_iter().map(|w| w.name).collect())
+        } else {
+            None
+        };
+        Ok(CommandEnv {
+            registry: self.command_registry().await,
+            vault_dir,
+            note,
+            workspaces,
+        })
+    }
+
+    /// Plans the `%%` commands of a document, or only the one on `line`, along with
+    /// the edits retargeting links in other notes to the note if a command moves
+    /// it. The note's own relative links are rewritten for its new folder in the
+    /// returned text, as its whole text is replaced.
+    async fn plan_commands(
+        &self,
+        text: &str,
+        env: &CommandEnv,
+        line: Option<usize>,
+    ) -> (ProcessedCommands, Vec<TextDocumentEdit>) {
+        let processed =
+            custom_commands::process_custom_commands(text, &env.registry, &env.context(), line);
+        let (own, others) = self
+            .move_link_edits(&processed.mutations, Some(&env.note))
+            .await;
+        if own.is_empty() {
+            return (processed, others);
+        }
+        let text = apply_text_edits(text, own);
+        let processed =
+            custom_commands::process_custom_commands(&text, &env.registry, &env.context(), line);
+        (processed, others)
+    }
+
+    /// Edits retargeting every link to the notes that `mutations` move, built from
+    /// the vault index like a rename. The edits of `note` itself are returned
+    /// apart, for callers that replace its whole text.
+    async fn move_link_edits(
+        &self,
+        mutations: &[Mutation],
+        note: Option<&Path>,
+    ) -> (Vec<TextEdit>, Vec<TextDocumentEdit>) {
+        // Lock order: vaults, then documents.
+        let vaults = self.vaults.read().await;
+        let documents = self.documents.read().await;
+        let mut own = Vec::new();
+        let mut others = Vec::new();
+        for mutation in mutations {
+            let Mutation::MoveNote { from, to } = mutation else {
+                continue;
+            };
+            let Some(index) = vaults.containing(from) else {
+                continue;
+            };
+            let (Some(old), Some(new)) = (index.relative_path(from), index.relative_path(to))
+            else {
+                continue;
+            };
+            let mut edits = rename::link_edits(index, &[(old.clone(), new)]);
+            if note == Some(from.as_path()) {
+                own.extend(edits.remove(&old).unwrap_or_default());
+            }
+            others.extend(rename::text_document_edits(index, &documents, edits));
+        }
+        (own, others)
+    }
+
+    /// Applies the vault mutations of `%%` commands and records them for undo.
+    /// Moves are done by the editor with the edit of the note, so this is only
+    /// called once that edit is known to apply; they are just recorded.
+    async fn run_mutations(&self, vault_dir: &Path, mutations: Vec<Mutation>) {
+        let mut applied = Vec::new();
+        for mutation in mutations.into_iter().filter(|m| !m.is_document_edit()) {
+            if mutation.rename_file().is_some() {
+                applied.push(mutation);
+                continue;
+            }
+            match apply_mutation(&mutation, vault_dir).await {
+                Ok(()) => applied.push(mutation),
+                Err(e) => self.client.show_message(MessageType::ERROR, e).await,
+            }
+        }
+        if !applied.is_empty() {
+            self.forget_workspaces().await;
+            self.command_history
+                .lock()
+                .unwrap()
+                .push((vault_dir.to_path_buf(), applied));
+        }
+    }
+
+    /// Runs a workspace command and returns its result as JSON, along with a message
+    /// to show the user. Changes to a workspace are applied and recorded like the
+    /// mutations of `%%` commands, so `notemancy.commands.undo` reverts them too.
+    async fn workspace_command(
+        &self,
+        command: &str,
+        args: &WorkspaceArgs,
+        vault_dir: &Path,
+    ) -> Result<(serde_json::Value, String), String> {
+        if command == workspace_commands::LIST {
+            let dir = vault_dir.to_path_buf();
+            let workspaces =
+                tokio::task::spawn_blocking(move || workspace_commands::list_workspaces(&dir))
+                    .await
+                    .unwrap_or_else(|e| Err(format!("Listing workspaces panicked: {}", e)))?;
+            let message = format!("{} workspaces", workspaces.len());
+            return Ok((serde_json::json!({ "workspaces": workspaces }), message));
+        }
+
+        let (mutation, value) = workspace_commands::mutation(command, args)?;
+        apply_mutation(&mutation, vault_dir).await?;
+        let message = mutation.describe();
+        self.forget_workspaces().await;
+        self.command_history
+            .lock()
+            .unwrap()
+            .push((vault_dir.to_path_buf(), vec![mutation]));
+        Ok((value, message))
+    }
+
+    /// What the `%%` commands of a document would do, without doing it: the planned
+    /// mutations, commands that cannot be planned and problems the vault reports.
+    async fn preview_commands(&self, uri: &Url) -> Result<serde_json::Value, String> {
+        let text = self
+            .get_document_text(uri)
+            .await
+            .ok_or("Document is not open")?;
+        let env = self.command_env(uri, true).await?;
+        let processed =
+            custom_commands::process_custom_commands(&text, &env.registry, &env.context(), None);
+        let mutations: Vec<serde_json::Value> = processed
+            .mutations
+            .iter()
+            .map(|mutation| {
+                let mut value = serde_json::to_value(mutation).unwrap_or_default();
+                value["description"] = mutation.describe().into();
+                value
+            })
+            .collect();
+        let settings = self.settings.read().await.diagnostics.clone();
+        let problems: Vec<serde_json::Value> =
+            diagnostics::command_diagnostics(&text, &env.registry, Some(&env.context()), &settings)
+                .into_iter()
+                .map(|d| serde_json::json!({ "line": d.range.start.line, "message": d.message }))
+                .collect();
+        Ok(serde_json::json!({
+            "mutations": mutations,
+            "errors": processed.errors,
+            "problems": problems,
+        }))
+    }
+
+    /// Runs the `%%` commands of a document, or only the one on `line`: the editor
+    /// removes their lines (and moves the note) first, then the other vault
+    /// mutations are applied.
+    async fn run_commands(
+        &self,
+        uri: &Url,
+        line: Option<u32>,
+    ) -> Result<serde_json::Value, String> {
+        let (text, version, end) = {
+            let docs = self.documents.read().await;
+            let doc = docs.get(uri).ok_or("Document is not open")?;
+            (doc.text(), doc.version(), doc.end_position())
+        };
+        let env = self.command_env(uri, false).await?;
+        let (processed, links) = self
+            .plan_commands(&text, &env, line.map(|line| line as usize))
+            .await;
+        let done: Vec<String> = processed.mutations.iter().map(Mutation::describe).collect();
+        if done.is_empty() {
+            return Ok(serde_json::json!({ "mutations": done, "errors": processed.errors }));
+        }
+
+        let renames = processed
+            .mutations
+            .iter()
+            .filter_map(Mutation::rename_file)
+            .collect();
+        let edit = TextEdit {
+            range: Range {
+                start: Position {
+                    line: 0,
+                    character: 0,
+                },
+                end,
+            },
+            new_text: processed.text,
+        };
+        let applied = self
+            .client
+            .apply_edit(note_edit(uri.clone(), version, edit, links, renames))
+            .await
+            .map_err(|e| e.to_string())?;
+        if !applied.applied {
+            return Err("The editor did not remove the command lines".to_string());
+        }
+        // Only touch the vault once the command lines are gone, so a rejected edit
+        // leaves everything as it was.
+        self.run_mutations(&env.vault_dir, processed.mutations)
+            .await;
+        Ok(serde_json::json!({ "mutations": done, "errors": processed.errors }))
+    }
+
+    /// Reverts the vault mutations of the last document whose commands ran. Every
+    /// mutation is tried; those that could not be reverted stay in the history so
+    /// undoing again retries them. Those that cannot be reverted at all, such as
+    /// shell commands, are reported as `notUndone`.
+    async fn undo_commands(&self) -> Result<serde_json::Value, String> {
+        let last = self.command_history.lock().unwrap().pop();
+        let Some((vault_dir, mutations)) = last else {
+            return Err("No commands to undo".to_string());
+        };
+        self.forget_workspaces().await;
+        let mut undone = Vec::new();
+        let mut not_undone = Vec::new();
+        let mut errors = Vec::new();
+        // Indexes of the mutations still in effect.
+        let mut remaining = Vec::new();
+        let mut moves = Vec::new();
+        for (i, mutation) in mutations.iter().enumerate().rev() {
+            let Some(inverse) = mutation.undo() else {
+                not_undone.push(mutation.describe());
+                continue;
+            };
+            if let Some(rename) = inverse.rename_file() {
+                moves.push((i, inverse, rename));
+                continue;
+            }
+            match apply_mutation(&inverse, &vault_dir).await {
+                Ok(()) => undone.push(inverse.describe()),
+                Err(e) => {
+                    errors.push(e);
+                    remaining.push(i);
+                }
+            }
+        }
+        if !moves.is_empty() {
+            let inverses: Vec<Mutation> = moves.iter().map(|(_, m, _)| m.clone()).collect();
+            let (_, links) = self.move_link_edits(&inverses, None).await;
+            let mut operations: Vec<DocumentChangeOperation> = links
+                .into_iter()
+                .map(DocumentChangeOperation::Edit)
+                .collect();
+            operations.extend(moves.iter().map(|(_, _, rename)| {
+                DocumentChangeOperation::Op(ResourceOp::Rename(rename.clone()))
+            }));
+            let edit = WorkspaceEdit {
+                document_changes: Some(DocumentChanges::Operations(operations)),
+                ..Default::default()
+            };
+            match self.client.apply_edit(edit).await {
+                Ok(response) if response.applied => {
+                    undone.extend(moves.iter().map(|(_, inverse, _)| inverse.describe()));
+                }
+                result => {
+                    errors.push(match result {
+                        Err(e) => e.to_string(),
+                        Ok(_) => "The editor did not move the note back".to_string(),
+                    });
+                    remaining.extend(moves.iter().map(|(i, _, _)| *i));
+                }
+            }
+        }
+
+        if !not_undone.is_empty() {
+            self.client
+                .show_message(
+                    MessageType::WARNING,
+                    format!("Cannot be undone: {}", not_undone.join("; ")),
+                )
+                .await;
+        }
+        if errors.is_empty() {
+            return Ok(serde_json::json!({ "undone": undone, "notUndone": not_undone }));
+        }
+        let kept: Vec<Mutation> = mutations
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| remaining.contains(i))
+            .map(|(_, mutation)| mutation)
+            .collect();
+        self.command_history.lock().unwrap().push((vault_dir, kept));
+        Err(format!(
+            "Undid {} of {} changes, undo again to retry the rest: {}",
+            undone.len(),
+            undone.len() + remaining.len(),
+            errors.join("; ")
+        ))
+    }
+
+    /// Opens the vaults again from the current settings and config, re-applying
+    /// the contents of open documents. The old vaults keep answering requests until
+    /// the new ones are built, and builds started before the reopen are discarded.
+    async fn reopen_vaults(&self) {
+        let generation = self.vault_generation.fetch_add(1, Ordering::SeqCst) + 1;
+        let (fallback, mut to_open) = self.initial_vaults().await;
+        // Also build the vaults of open documents, like `ensure_vault_for` would.
+        let config = if self.settings.read().await.vault_path.is_some() {
+            None
+        } else {
+            self.config.read().await.clone()
+        };
+        let uris = self.documents.read().await.uris();
+        let document_vaults = uris
+            .iter()
+            .filter_map(|uri| uri.to_file_path().ok())
+            .filter_map(|path| config.as_ref()?.vault_for_path(&path).cloned());
+        for vault in document_vaults {
+            if !to_open.iter().any(|v| v.name == vault.name) {
+                to_open.push(vault);
+            }
+        }
+
+        let mut set = VaultSet::default();
+        if let Some(fallback) = fallback {
+            set.set_fallback(fallback);
+        }
+        for vault in to_open {
+            if let Some(index) = self.build_vault(vault).await {
+                set.insert(index);
+            }
+        }
+        let roots: Vec<PathBuf> = set.iter().map(|index| index.root().to_path_buf()).collect();
+        {
+            // Lock order: vaults, then documents.
+            let mut vaults = self.vaults.write().await;
+            // A later reopen is building newer vaults.
+            if generation != self.vault_generation.load(Ordering::SeqCst) {
+                return;
+            }
+            let docs = self.documents.read().await;
+            for uri in docs.uris() {
+                let Ok(path) = uri.to_file_path() else {
+                    continue;
+                };
+                let (Some(index), Some(text)) = (set.containing_mut(&path), docs.text(&uri)) else {
+                    continue;
+                };
+                if let Some(relative) = index.relative_path(&path) {
+                    index.update_note(&relative, &text);
+                }
+            }
+            *vaults = set;
+        }
+
+        self.fs_watchers.lock().unwrap().clear();
+        for root in roots {
+            self.watch_vault(&root).await;
+        }
+        // Documents opened during the reopen may belong to a vault it did not build.
+        let uris = self.documents.read().await.uris();
+        for uri in uris {
+            self.ensure_vault_for(&uri).await;
+        }
+        self.publish_all_diagnostics().await;
+    }
+
+    /// Reloads config.yaml and, if it changed, reopens the vaults.
+    async fn reload_config(&self) {
+        if load_config(&self.client, &self.config).await
+            && self.settings.read().await.vault_path.is_none()
+        {
+            self.reopen_vaults().await;
+        }
+    }
+
+    /// Starts an internal watcher on config.yaml unless the client watches files for us.
+    /// Changes are handled like `workspace/didChangeWatchedFiles` for config.yaml.
+    fn watch_config(&self) {
+        if self.client_watches_files.load(Ordering::Relaxed) {
+            return;
+        }
+        let Ok(path) = config::config_path() else {
+            return;
+        };
+        let Ok((w, mut rx)) = watcher::spawn_file_watcher(&path) else {
+            return;
+        };
+        *self.config_watcher.lock().unwrap() = Some(w);
+
+        let server = self.clone();
+        tokio::spawn(async move {
+            while rx.recv().await.is_some() {
+                server.reload_config().await;
+            }
+        });
+    }
+
+    /// Computes a document's semantic tokens and remembers them under a new result ID.
+    /// Returns the result ID, the new tokens and the previously sent ones.
+    async fn compute_semantic_tokens(
+        &self,
+        uri: &Url,
+    ) -> Option<(
+        String,
+        Vec<SemanticToken>,
+        Option<(String, Vec<SemanticToken>)>,
+    )> {
+        let text = self.get_document_text(uri).await?;
+        let registry = self.command_registry().await;
+        let tokens = {
+            let vaults = self.vaults.read().await;
+            semantic_tokens::semantic_tokens(&text, vaults.for_uri(uri)?, &registry)
+        };
+        let result_id = self
+            .next_result_id
+            .fetch_add(1, Ordering::Relaxed)
+            .to_string();
+        let previous = self
+            .semantic_tokens
+            .write()
+            .await
+            .insert(uri.clone(), (result_id.clone(), tokens.clone()));
+        Some((result_id, tokens, previous))
+    }
+
+    /// Publishes diagnostics for every open document.
+    async fn publish_all_diagnostics(&self) {
+        let uris = self.documents.read().await.uris();
+        for uri in uris {
+            self.publish_diagnostics(uri).await;
+        }
+    }
+
+    /// Re-parses an open document into the index so queries see unsaved edits.
+    async fn reindex_document(&self, uri: &Url, text: &str) {
+        let Ok(path) = uri.to_file_path() else {
+            return;
+        };
+        let mut vaults = self.vaults.write().await;
+        if let Some(index) = vaults.containing_mut(&path) {
+            if let Some(relative) = index.relative_path(&path) {
+                index.update_note(&relative, text);
+            }
+        }
+    }
+}
+
+/// Applies a vault mutation on the blocking pool, as it may touch the disk or run
+/// a shell command.
+async fn apply_mutation(mutation: &Mutation, vault_dir: &Path) -> Result<(), String> {
+    let (mutation, vault_dir) = (mutation.clone(), vault_dir.to_path_buf());
+    tokio::task::spawn_blocking(move || mutation.apply(&vault_dir))
+        .await
+        .unwrap_or_else(|e| Err(format!("Command panicked: {}", e)))
+}
+
+/// An edit of a note and of the links to it in other notes, followed by file
+/// renames, typically moving the note itself.
+fn note_edit(
+    uri: Url,
+    version: i32,
+    edit: TextEdit,
+    links: Vec<TextDocumentEdit>,
+    renames: Vec<RenameFile>,
+) -> WorkspaceEdit {
+    let mut operations = vec![DocumentChangeOperation::Edit(TextDocumentEdit {
+        text_document: OptionalVersionedTextDocumentIdentifier {
+            uri,
+            version: Some(version),
+        },
+        edits: vec![OneOf::Left(edit)],
+    })];
+    operations.extend(links.into_iter().map(DocumentChangeOperation::Edit));
+    operations.extend(
+        renames
+            .into_iter()
+            .map(|rename| DocumentChangeOperation::Op(ResourceOp::Rename(rename))),
+    );
+    WorkspaceEdit {
+        document_changes: Some(DocumentChanges::Operations(operations)),
+        ..Default::default()
+    }
+}
+
+/// Applies non-overlapping edits to a text.
+fn apply_text_edits(text: &str, mut edits: Vec<TextEdit>) -> String {
+    let mut document = Document::new(text, 0);
+    // From the end, so the positions of the edits before stay valid.
+    edits.sort_by_key(|edit| edit.range.start);
+    for edit in edits.into_iter().rev() {
+        document.apply_change(TextDocumentContentChangeEvent {
+            range: Some(edit.range),
+            range_length: None,
+            text: edit.new_text,
+        });
+    }
+    document.text()
+}
+
+/// Loads config.yaml into `config` and publishes its problems as diagnostics on
+/// the file. A config that fails to parse leaves the previous one in place.
+/// Returns whether the config changed.
+async fn load_config(client: &Client, config: &RwLock<Option<ConfigFile>>) -> bool {
+    let (path, text) = match config::read_config() {
+        Ok(read) => read,
+        Err(e) => {
+            client
+                .log_message(MessageType::ERROR, format!("Failed to load config: {}", e))
+                .await;
+            return false;
+        }
+    };
+
+    let parsed = ConfigFile::parse(&text);
+    if let Ok(uri) = Url::from_file_path(&path) {
+        let diagnostics = diagnostics::config_diagnostics(&text, &parsed);
+        client.publish_diagnostics(uri, diagnostics, None).await;
+    }
+
+    match parsed {
+        Ok(parsed) => {
+            let mut config = config.write().await;
+            let changed = config.as_ref() != Some(&parsed);
+            *config = Some(parsed);
+            changed
+        }
+        Err(e) => {
+            client
+                .log_message(
+                    MessageType::ERROR,
+                    format!("Failed to parse {}: {}", path.display(), e),
+                )
+                .await;
+            false
+        }
     }
 }
 
@@ -39,25 +1033,106 @@ impl NotemancyServer {
 impl LanguageServer for NotemancyServer {
     async fn initialize(
         &self,
-        _params: InitializeParams,
+        params: InitializeParams,
     ) -> Result<InitializeResult, tower_lsp::jsonrpc::Error> {
+        let client_watches_files = params
+            .capabilities
+            .workspace
+            .as_ref()
+            .and_then(|ws| ws.did_change_watched_files.as_ref())
+            .and_then(|w| w.dynamic_registration)
+            .unwrap_or(false);
+        self.client_watches_files
+            .store(client_watches_files, Ordering::Relaxed);
+
+        let folders: Vec<PathBuf> = match &params.workspace_folders {
+            Some(folders) => folders
+                .iter()
+                .filter_map(|f| f.uri.to_file_path().ok())
+                .collect(),
+            None => params
+                .root_uri
+                .iter()
+                .filter_map(|uri| uri.to_file_path().ok())
+                .collect(),
+        };
+        *self.workspace_folders.write().await = folders;
+
+        if let Some(options) = params.initialization_options {
+            match Settings::from_value(options) {
+                Ok(settings) => *self.settings.write().await = settings,
+                Err(e) => {
+                    self.client.log_message(MessageType::WARNING, e).await;
+                }
+            }
+        }
+
         self.client
             .log_message(MessageType::INFO, "Notemancy LSP initialized")
             .await;
         Ok(InitializeResult {
             capabilities: ServerCapabilities {
                 document_symbol_provider: Some(OneOf::Left(true)),
+                document_link_provider: Some(DocumentLinkOptions {
+                    resolve_provider: Some(false),
+                    work_done_progress_options: Default::default(),
+                }),
+                folding_range_provider: Some(FoldingRangeProviderCapability::Simple(true)),
+                semantic_tokens_provider: Some(
+                    SemanticTokensServerCapabilities::SemanticTokensOptions(
+                        SemanticTokensOptions {
+                            legend: semantic_tokens::legend(),
+                            full: Some(SemanticTokensFullOptions::Delta { delta: Some(true) }),
+                            range: None,
+                            work_done_progress_options: Default::default(),
+                        },
+                    ),
+                ),
                 workspace_symbol_provider: Some(OneOf::Left(true)),
                 completion_provider: Some(CompletionOptions {
-                    resolve_provider: Some(false),
-                    trigger_characters: Some(vec!["[".to_string()]),
+                    resolve_provider: Some(true),
+                    trigger_characters: Some(vec![
+                        "[".to_string(),
+                        "#".to_string(),
+                        "%".to_string(),
+                    ]),
                     ..Default::default()
                 }),
-                text_document_sync: Some(TextDocumentSyncCapability::Kind(
-                    TextDocumentSyncKind::FULL,
+                text_document_sync: Some(TextDocumentSyncCapability::Options(
+                    TextDocumentSyncOptions {
+                        open_close: Some(true),
+                        change: Some(TextDocumentSyncKind::INCREMENTAL),
+                        ..Default::default()
+                    },
                 )),
                 definition_provider: Some(OneOf::Left(true)),
+                references_provider: Some(OneOf::Left(true)),
+                rename_provider: Some(OneOf::Right(RenameOptions {
+                    prepare_provider: Some(true),
+                    work_done_progress_options: Default::default(),
+                })),
+                workspace: Some(WorkspaceServerCapabilities {
+                    workspace_folders: None,
+                    file_operations: Some(WorkspaceFileOperationsServerCapabilities {
+                        will_rename: Some(FileOperationRegistrationOptions {
+                            filters: vec![FileOperationFilter {
+                                scheme: Some("file".to_string()),
+                                pattern: FileOperationPattern {
+                                    glob: "**/*.md".to_string(),
+                                    matches: None,
+                                    options: None,
+                                },
+                            }],
+                        }),
+                        ..Default::default()
+                    }),
+                }),
                 hover_provider: Some(HoverProviderCapability::Simple(true)),
+                code_action_provider: Some(CodeActionProviderCapability::Simple(true)),
+                execute_command_provider: Some(ExecuteCommandOptions {
+                    commands: [workspace_commands::commands(), commands::commands()].concat(),
+                    work_done_progress_options: Default::default(),
+                }),
                 document_formatting_provider: Some(OneOf::Left(true)), // Advertise formatting support
                 ..Default::default()
             },
@@ -69,31 +1144,118 @@ impl LanguageServer for NotemancyServer {
         self.client
             .show_message(MessageType::INFO, "Notemancy LSP is ready")
             .await;
+        self.register_file_watcher().await;
+        load_config(&self.client, &self.config).await;
+        self.watch_config();
+        self.open_initial_vaults().await;
+
+        // Documents opened while the index was building have not been checked yet.
+        self.publish_all_diagnostics().await;
+    }
+
+    async fn did_change_configuration(&self, params: DidChangeConfigurationParams) {
+        let settings = match Settings::from_value(params.settings) {
+            Ok(settings) => settings,
+            Err(e) => {
+                self.client.log_message(MessageType::WARNING, e).await;
+                return;
+            }
+        };
+        let vault_changed = self.settings.read().await.vault_path != settings.vault_path;
+        *self.settings.write().await = settings;
+
+        if vault_changed {
+            self.reopen_vaults().await;
+        } else {
+            self.publish_all_diagnostics().await;
+        }
     }
 
     async fn did_open(&self, params: DidOpenTextDocumentParams) {
         let text_doc = params.text_document;
+        self.ensure_vault_for(&text_doc.uri).await;
+        self.reindex_document(&text_doc.uri, &text_doc.text).await;
         self.documents
             .write()
             .await
-            .insert(text_doc.uri, text_doc.text);
+            .open(text_doc.uri.clone(), &text_doc.text, text_doc.version);
+        self.publish_diagnostics(text_doc.uri).await;
     }
 
     async fn did_change(&self, params: DidChangeTextDocumentParams) {
         let uri = params.text_document.uri;
-        if let Some(change) = params.content_changes.into_iter().last() {
-            self.documents.write().await.insert(uri, change.text);
+        let version = params.text_document.version;
+        let result =
+            self.documents
+                .write()
+                .await
+                .apply_changes(&uri, version, params.content_changes);
+        if let Err(e) = result {
+            self.client.log_message(MessageType::WARNING, e).await;
+            return;
+        }
+        if let Some(text) = self.get_document_text(&uri).await {
+            self.reindex_document(&uri, &text).await;
+        }
+        self.publish_diagnostics(uri).await;
+    }
+
+    async fn did_close(&self, params: DidCloseTextDocumentParams) {
+        let uri = params.text_document.uri;
+        self.documents.write().await.close(&uri);
+        self.semantic_tokens.write().await.remove(&uri);
+        // The index holds the buffer's contents, which may never have been saved.
+        // Read the note before taking the lock, so requests are not held up.
+        if let Ok(path) = uri.to_file_path() {
+            let in_vault = self.vaults.read().await.containing(&path).is_some();
+            let note = if in_vault {
+                tokio::task::spawn_blocking(move || DiskNote::read(path))
+                    .await
+                    .ok()
+            } else {
+                None
+            };
+            if let Some(note) = note {
+                let mut vaults = self.vaults.write().await;
+                if let Some(index) = vaults.containing_mut(note.path()) {
+                    index.apply_disk_note(note);
+                }
+            }
+        }
+        self.client.publish_diagnostics(uri, Vec::new(), None).await;
+    }
+
+    async fn did_change_watched_files(&self, params: DidChangeWatchedFilesParams) {
+        if let Ok(config_path) = config::config_path() {
+            let config_changed = params
+                .changes
+                .iter()
+                .any(|change| change.uri.to_file_path().is_ok_and(|p| p == config_path));
+            if config_changed {
+                self.reload_config().await;
+            }
         }
+
+        let events = params
+            .changes
+            .iter()
+            .filter_map(WatchEvent::from_file_event)
+            .collect();
+        watcher::apply_events(events, &self.vaults, &self.documents).await;
+
+        // Links in open documents may have started or stopped resolving.
+        self.publish_all_diagnostics().await;
     }
 
     async fn document_symbol(
         &self,
         params: DocumentSymbolParams,
     ) -> Result<Option<DocumentSymbolResponse>, tower_lsp::jsonrpc::Error> {
+        if !self.settings.read().await.features.document_symbols {
+            return Ok(None);
+        }
         let uri = params.text_document.uri;
-        let docs = self.documents.read().await;
-        let text = docs.get(&uri).cloned().unwrap_or_default();
-        drop(docs);
+        let text = self.get_document_text(&uri).await.unwrap_or_default();
 
         let spinner = indicatif::ProgressBar::new_spinner();
         spinner.set_message("Processing document symbols...");
@@ -106,12 +1268,95 @@ impl LanguageServer for NotemancyServer {
         Ok(Some(DocumentSymbolResponse::Nested(symbols)))
     }
 
+    async fn document_link(
+        &self,
+        params: DocumentLinkParams,
+    ) -> Result<Option<Vec<DocumentLink>>, tower_lsp::jsonrpc::Error> {
+        if !self.settings.read().await.features.document_links {
+            return Ok(None);
+        }
+        let uri = params.text_document.uri;
+        let text = self.get_document_text(&uri).await.unwrap_or_default();
+        let vaults = self.vaults.read().await;
+        let Some(index) = vaults.for_uri(&uri) else {
+            return Ok(None);
+        };
+        Ok(Some(document_links::document_links(&uri, &text, index)))
+    }
+
+    async fn folding_range(
+        &self,
+        params: FoldingRangeParams,
+    ) -> Result<Option<Vec<FoldingRange>>, tower_lsp::jsonrpc::Error> {
+        if !self.settings.read().await.features.folding_ranges {
+            return Ok(None);
+        }
+        let text = self
+            .get_document_text(&params.text_document.uri)
+            .await
+            .unwrap_or_default();
+        Ok(Some(folding::folding_ranges(&text)))
+    }
+
+    async fn semantic_tokens_full(
+        &self,
+        params: SemanticTokensParams,
+    ) -> Result<Option<SemanticTokensResult>, tower_lsp::jsonrpc::Error> {
+        if !self.settings.read().await.features.semantic_tokens {
+            return Ok(None);
+        }
+        let Some((result_id, data, _)) = self
+            .compute_semantic_tokens(&params.text_document.uri)
+            .await
+        else {
+            return Ok(None);
+        };
+        Ok(Some(SemanticTokensResult::Tokens(SemanticTokens {
+            result_id: Some(result_id),
+            data,
+        })))
+    }
+
+    async fn semantic_tokens_full_delta(
+        &self,
+        params: SemanticTokensDeltaParams,
+    ) -> Result<Option<SemanticTokensFullDeltaResult>, tower_lsp::jsonrpc::Error> {
+        if !self.settings.read().await.features.semantic_tokens {
+            return Ok(None);
+        }
+        let Some((result_id, data, previous)) = self
+            .compute_semantic_tokens(&params.text_document.uri)
+            .await
+        else {
+            return Ok(None);
+        };
+        // Send a delta only against the result the client has; otherwise start over.
+        match previous {
+            Some((previous_id, previous)) if previous_id == params.previous_result_id => Ok(Some(
+                SemanticTokensFullDeltaResult::TokensDelta(SemanticTokensDelta {
+                    result_id: Some(result_id),
+                    edits: semantic_tokens::diff(&previous, &data),
+                }),
+            )),
+            _ => Ok(Some(SemanticTokensFullDeltaResult::Tokens(
+                SemanticTokens {
+                    result_id: Some(result_id),
+                    data,
+                },
+            ))),
+        }
+    }
+
     async fn symbol(
         &self,
         params: WorkspaceSymbolParams,
     ) -> Result<Option<Vec<SymbolInformation>>, tower_lsp::jsonrpc::Error> {
+        if !self.settings.read().await.features.workspace_symbols {
+            return Ok(None);
+        }
         let query = params.query;
-        let symbols = workspace_symbols::get_workspace_symbols(&query).map_err(|e| {
+        let vaults = self.vaults.read().await;
+        let symbols = workspace_symbols::get_workspace_symbols(&query, &vaults).map_err(|e| {
             tower_lsp::jsonrpc::Error {
                 code: tower_lsp::jsonrpc::ErrorCode::InternalError,
                 message: e,
@@ -125,41 +1370,201 @@ impl LanguageServer for NotemancyServer {
         &self,
         params: CompletionParams,
     ) -> Result<Option<CompletionResponse>, tower_lsp::jsonrpc::Error> {
+        if !self.settings.read().await.features.completion {
+            return Ok(None);
+        }
         let uri = params.text_document_position.text_document.uri.clone();
-        let docs = self.documents.read().await;
-        let text = docs.get(&uri).cloned().unwrap_or_default();
-        drop(docs);
-        completion::provide_wiki_link_completions(params, &text)
+        let text = self.get_document_text(&uri).await.unwrap_or_default();
+        let position = params.text_document_position.position;
+        let on_command_line = text
+            .lines()
+            .nth(position.line as usize)
+            .is_some_and(|line| line.trim_start().starts_with("%%"));
+        if on_command_line {
+            let Ok(env) = self.command_env(&uri, true).await else {
+                return Ok(None);
+            };
+            return Ok(custom_commands::command_completions(
+                &text,
+                position,
+                &env.registry,
+                &env.context(),
+            ));
+        }
+        let vaults = self.vaults.read().await;
+        let Some(index) = vaults.for_uri(&uri) else {
+            return Ok(None);
+        };
+        if let Some(response) = completion::provide_wiki_link_completions(params, &text, index)? {
+            return Ok(Some(response));
+        }
+        if let Some(response) = tags::tag_completions(&text, position, index) {
+            return Ok(Some(response));
+        }
+        Ok(frontmatter::frontmatter_completions(
+            &text,
+            position,
+            index.schema(),
+        ))
+    }
+
+    async fn completion_resolve(
+        &self,
+        item: CompletionItem,
+    ) -> Result<CompletionItem, tower_lsp::jsonrpc::Error> {
+        let vaults = self.vaults.read().await;
+        Ok(completion::resolve_completion_item(item, &vaults))
     }
 
     async fn goto_definition(
         &self,
         params: GotoDefinitionParams,
     ) -> Result<Option<GotoDefinitionResponse>, tower_lsp::jsonrpc::Error> {
+        if !self.settings.read().await.features.definition {
+            return Ok(None);
+        }
         let td_params = params.text_document_position_params;
         let uri = td_params.text_document.uri.clone();
-        let docs = self.documents.read().await;
-        let text = docs.get(&uri).cloned().unwrap_or_default();
-        drop(docs);
+        let text = self.get_document_text(&uri).await.unwrap_or_default();
 
-        if let Some(location) = goto_wikilink(&text, td_params.position) {
-            Ok(Some(GotoDefinitionResponse::Scalar(location)))
-        } else {
-            Ok(None)
+        let vaults = self.vaults.read().await;
+        let Some(index) = vaults.for_uri(&uri) else {
+            return Ok(None);
+        };
+        let location = goto_wikilink(&uri, &text, td_params.position, index)
+            .or_else(|| goto_markdown_link(&uri, &text, td_params.position, index));
+        Ok(location.map(GotoDefinitionResponse::Scalar))
+    }
+
+    async fn references(
+        &self,
+        params: ReferenceParams,
+    ) -> Result<Option<Vec<Location>>, tower_lsp::jsonrpc::Error> {
+        if !self.settings.read().await.features.references {
+            return Ok(None);
+        }
+        let td_params = params.text_document_position;
+        let uri = td_params.text_document.uri;
+        let text = self.get_document_text(&uri).await.unwrap_or_default();
+
+        let vaults = self.vaults.read().await;
+        let Some(index) = vaults.for_uri(&uri) else {
+            return Ok(None);
+        };
+        if let Some(locations) = tags::tag_references(&uri, &text, td_params.position, index) {
+            return Ok(Some(locations));
         }
+        let locations = references::find_references(
+            &uri,
+            &text,
+            td_params.position,
+            params.context.include_declaration,
+            index,
+        );
+        Ok(Some(locations))
+    }
+
+    async fn prepare_rename(
+        &self,
+        params: TextDocumentPositionParams,
+    ) -> Result<Option<PrepareRenameResponse>, tower_lsp::jsonrpc::Error> {
+        if !self.settings.read().await.features.rename {
+            return Ok(None);
+        }
+        let uri = params.text_document.uri;
+        let text = self.get_document_text(&uri).await.unwrap_or_default();
+        let vaults = self.vaults.read().await;
+        let Some(index) = vaults.for_uri(&uri) else {
+            return Ok(None);
+        };
+        Ok(rename::prepare_rename(&uri, &text, params.position, index))
+    }
+
+    async fn rename(
+        &self,
+        params: RenameParams,
+    ) -> Result<Option<WorkspaceEdit>, tower_lsp::jsonrpc::Error> {
+        if !self.settings.read().await.features.rename {
+            return Ok(None);
+        }
+        let td_params = params.text_document_position;
+        let uri = td_params.text_document.uri;
+        let text = self.get_document_text(&uri).await.unwrap_or_default();
+
+        let vaults = self.vaults.read().await;
+        let Some(index) = vaults.for_uri(&uri) else {
+            return Ok(None);
+        };
+        // Lock order: `vaults`, then `documents`.
+        let documents = self.documents.read().await;
+        rename::rename(
+            &uri,
+            &text,
+            td_params.position,
+            &params.new_name,
+            index,
+            &documents,
+        )
+        .map_err(|e| tower_lsp::jsonrpc::Error {
+            code: tower_lsp::jsonrpc::ErrorCode::InvalidParams,
+            message: e,
+            data: None,
+        })
+    }
+
+    async fn will_rename_files(
+        &self,
+        params: RenameFilesParams,
+    ) -> Result<Option<WorkspaceEdit>, tower_lsp::jsonrpc::Error> {
+        if !self.settings.read().await.features.rename {
+            return Ok(None);
+        }
+        // Lock order: `vaults`, then `documents`.
+        let vaults = self.vaults.read().await;
+        let documents = self.documents.read().await;
+        Ok(rename::will_rename_files(&params, &vaults, &documents))
     }
 
     async fn formatting(
         &self,
         params: DocumentFormattingParams,
     ) -> Result<Option<Vec<TextEdit>>, tower_lsp::jsonrpc::Error> {
+        if !self.settings.read().await.features.formatting {
+            return Ok(None);
+        }
         let uri = params.text_document.uri;
-        let docs = self.documents.read().await;
-        let text = docs.get(&uri).cloned().unwrap_or_default();
-        drop(docs);
+        let (text, version, end) = {
+            let docs = self.documents.read().await;
+            match docs.get(&uri) {
+                Some(doc) => (doc.text(), doc.version(), doc.end_position()),
+                None => return Ok(None),
+            }
+        };
 
-        // Call the updated formatter with &text and &uri.
-        let formatted = crate::handlers::formatting::format_markdown(&text, &uri).map_err(|e| {
+        // With `runCommands` on, plan the `%%` commands and remove them from the text
+        // before formatting. Otherwise the formatter keeps their lines as they are.
+        let settings = self.settings.read().await.formatter.clone();
+        let (source, planned) = if settings.run_commands {
+            let env =
+                self.command_env(&uri, false)
+                    .await
+                    .map_err(|e| tower_lsp::jsonrpc::Error {
+                        code: tower_lsp::jsonrpc::ErrorCode::InternalError,
+                        message: e,
+                        data: None,
+                    })?;
+            let (processed, links) = self.plan_commands(&text, &env, None).await;
+            for e in processed.errors {
+                self.client.log_message(MessageType::WARNING, e).await;
+            }
+            (
+                processed.text,
+                Some((env.vault_dir, processed.mutations, links)),
+            )
+        } else {
+            (text.clone(), None)
+        };
+        let formatted = formatting::format_markdown(&source, &settings).map_err(|e| {
             tower_lsp::jsonrpc::Error {
                 code: tower_lsp::jsonrpc::ErrorCode::InternalError,
                 message: format!("Markdown formatting error: {}", e),
@@ -167,39 +1572,187 @@ impl LanguageServer for NotemancyServer {
             }
         })?;
 
-        if formatted == text {
-            Ok(None)
-        } else {
-            let lines: Vec<&str> = text.lines().collect();
-            let last_line_len = lines.last().map(|l| l.len() as u32).unwrap_or(0);
-            let full_range = tower_lsp::lsp_types::Range {
-                start: tower_lsp::lsp_types::Position {
-                    line: 0,
-                    character: 0,
-                },
-                end: tower_lsp::lsp_types::Position {
-                    line: lines.len() as u32,
-                    character: last_line_len,
-                },
-            };
-            let edit = tower_lsp::lsp_types::TextEdit {
-                range: full_range,
-                new_text: formatted,
-            };
-            Ok(Some(vec![edit]))
+        // Reject the result if the buffer changed while we were formatting.
+        if self.documents.read().await.version(&uri) != Some(version) {
+            return Err(tower_lsp::jsonrpc::Error {
+                code: tower_lsp::jsonrpc::ErrorCode::ContentModified,
+                message: "Document changed while formatting".to_string(),
+                data: None,
+            });
+        }
+
+        let full_range = tower_lsp::lsp_types::Range {
+            start: tower_lsp::lsp_types::Position {
+                line: 0,
+                character: 0,
+            },
+            end,
+        };
+        let edit = tower_lsp::lsp_types::TextEdit {
+            range: full_range,
+            new_text: formatted,
+        };
+        let (vault_dir, mutations, links) = planned.unwrap_or_default();
+        if mutations.is_empty() {
+            return Ok((edit.new_text != text).then(|| vec![edit]));
         }
+
+        // The commands change the vault: send the edit removing them, the links to a
+        // moved note and the move as one workspace edit so the editor's buffer
+        // follows the file, and run the commands only once it applied.
+        let renames: Vec<RenameFile> = mutations.iter().filter_map(Mutation::rename_file).collect();
+        let failure = if renames.is_empty() {
+            "The editor could not apply the commands"
+        } else {
+            "The editor could not move the note"
+        };
+        let edit = note_edit(uri, version, edit, links, renames);
+        let server = self.clone();
+        tokio::spawn(async move {
+            if server
+                .client
+                .apply_edit(edit)
+                .await
+                .is_ok_and(|r| r.applied)
+            {
+                server.run_mutations(&vault_dir, mutations).await;
+            } else {
+                server
+                    .client
+                    .show_message(MessageType::ERROR, failure)
+                    .await;
+            }
+        });
+        Ok(None)
     }
 
     async fn hover(&self, params: HoverParams) -> Result<Option<Hover>, tower_lsp::jsonrpc::Error> {
+        if !self.settings.read().await.features.hover {
+            return Ok(None);
+        }
         let uri = params.text_document_position_params.text_document.uri;
         let position = params.text_document_position_params.position;
 
         let document_text = self.get_document_text(&uri).await.unwrap_or_default();
+        let registry = self.command_registry().await;
+        if let Some(hover) = custom_commands::command_hover(&document_text, position, &registry) {
+            return Ok(Some(hover));
+        }
 
-        if let Some(hover) = hover_wikilink::hover_wikilink(&document_text, position) {
+        let vaults = self.vaults.read().await;
+        let Some(index) = vaults.for_uri(&uri) else {
+            return Ok(None);
+        };
+        if let Some(hover) = hover_wikilink::hover_wikilink(&document_text, position, index) {
+            Ok(Some(hover))
+        } else if let Some(hover) =
+            hover_wikilink::hover_markdown_link(&uri, &document_text, position, index)
+        {
             Ok(Some(hover))
         } else {
-            Ok(None)
+            Ok(frontmatter::frontmatter_hover(
+                &document_text,
+                position,
+                index.schema(),
+            ))
+        }
+    }
+
+    async fn code_action(
+        &self,
+        params: CodeActionParams,
+    ) -> Result<Option<CodeActionResponse>, tower_lsp::jsonrpc::Error> {
+        if !self.settings.read().await.features.code_actions {
+            return Ok(None);
+        }
+        let uri = params.text_document.uri;
+        let only = params.context.only.as_deref();
+        let wants_quickfix = code_actions::wants(only, &CodeActionKind::QUICKFIX);
+        let wants_refactor = code_actions::wants(only, &CodeActionKind::REFACTOR);
+        if !wants_quickfix && !wants_refactor {
+            return Ok(None);
+        }
+        let text = self.get_document_text(&uri).await.unwrap_or_default();
+        // Workspaces are only listed for the actions that need them.
+        let workspaces = if wants_refactor {
+            match self.workspaces_for(&uri).await {
+                Ok(workspaces) => workspaces,
+                Err(e) => {
+                    self.client.log_message(MessageType::WARNING, e).await;
+                    Vec::new()
+                }
+            }
+        } else {
+            Vec::new()
+        };
+        let registry = self.command_registry().await;
+        Ok(Some(code_actions::code_actions(
+            &uri,
+            &text,
+            params.range,
+            only,
+            &workspaces,
+            &registry,
+        )))
+    }
+
+    async fn execute_command(
+        &self,
+        params: ExecuteCommandParams,
+    ) -> Result<Option<serde_json::Value>, tower_lsp::jsonrpc::Error> {
+        if commands::commands().contains(&params.command) {
+            let args = WorkspaceArgs::parse(&params.arguments);
+            let line = args.as_ref().ok().and_then(|args| args.line);
+            let uri = args.and_then(|args| args.uri.ok_or_else(|| "Missing note URI".to_string()));
+            let result = match params.command.as_str() {
+                commands::UNDO_COMMAND => self.undo_commands().await,
+                commands::PREVIEW_COMMAND => match uri {
+                    Ok(uri) => self.preview_commands(&uri).await,
+                    Err(e) => Err(e),
+                },
+                _ => match uri {
+                    Ok(uri) => self.run_commands(&uri, line).await,
+                    Err(e) => Err(e),
+                },
+            };
+            return match result {
+                Ok(value) => Ok(Some(value)),
+                Err(e) => {
+                    self.client.show_message(MessageType::ERROR, &e).await;
+                    Err(tower_lsp::jsonrpc::Error {
+                        code: tower_lsp::jsonrpc::ErrorCode::InternalError,
+                        message: e,
+                        data: None,
+                    })
+                }
+            };
+        }
+        let args =
+            WorkspaceArgs::parse(&params.arguments).map_err(|e| tower_lsp::jsonrpc::Error {
+                code: tower_lsp::jsonrpc::ErrorCode::InvalidParams,
+                message: e,
+                data: None,
+            })?;
+        let result = match self.vault_dir(args.uri.as_ref()).await {
+            Ok(vault_dir) => {
+                self.workspace_command(&params.command, &args, &vault_dir)
+                    .await
+            }
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok((value, message)) => {
+                self.client.show_message(MessageType::INFO, message).await;
+                Ok(Some(value))
+            }
+            Err(e) => {
+                self.client.show_message(MessageType::ERROR, &e).await;
+                Err(tower_lsp::jsonrpc::Error {
+                    code: tower_lsp::jsonrpc::ErrorCode::InternalError,
+                    message: e,
+                    data: None,
+                })
+            }
         }
     }
 
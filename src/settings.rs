@@ -0,0 +1,169 @@
+// src/settings.rs
+use serde::Deserialize;
+use std::path::PathBuf;
+use tower_lsp::lsp_types::DiagnosticSeverity;
+
+/// Editor-provided settings, read from `initializationOptions` and refreshed on
+/// `workspace/didChangeConfiguration`. Anything left unset falls back to
+/// config.yaml and the built-in defaults.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Settings {
+    /// Overrides the vault directory from config.yaml.
+    pub vault_path: Option<PathBuf>,
+    pub formatter: FormatterSettings,
+    pub diagnostics: DiagnosticsSettings,
+    pub features: FeatureToggles,
+}
+
+impl Settings {
+    /// Parses settings sent by the client. They may be given directly or nested
+    /// under a `notemancy` key, as most editors namespace settings per server.
+    pub fn from_value(value: serde_json::Value) -> Result<Self, String> {
+        let value = match value {
+            serde_json::Value::Object(mut map) if map.contains_key("notemancy") => {
+                map.remove("notemancy").unwrap_or_default()
+            }
+            serde_json::Value::Null => return Ok(Self::default()),
+            other => other,
+        };
+        serde_json::from_value(value).map_err(|e| format!("Invalid settings: {}", e))
+    }
+}
+
+/// Markdown output style. Unset options keep pulldown-cmark-to-cmark's defaults.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct FormatterSettings {
+    pub list_token: Option<char>,
+    pub ordered_list_token: Option<char>,
+    pub increment_ordered_list_bullets: Option<bool>,
+    pub emphasis_token: Option<char>,
+    pub strong_token: Option<String>,
+    pub code_block_token: Option<char>,
+    /// Runs `%%` commands when formatting. Off by default so that formatting, e.g.
+    /// on save, never changes the vault; commands then run through
+    /// `notemancy.runCommands`.
+    pub run_commands: bool,
+}
+
+impl FormatterSettings {
+    pub fn cmark_options(&self) -> pulldown_cmark_to_cmark::Options<'_> {
+        let mut options = pulldown_cmark_to_cmark::Options::default();
+        if let Some(token) = self.list_token {
+            options.list_token = token;
+        }
+        if let Some(token) = self.ordered_list_token {
+            options.ordered_list_token = token;
+        }
+        if let Some(increment) = self.increment_ordered_list_bullets {
+            options.increment_ordered_list_bullets = increment;
+        }
+        if let Some(token) = self.emphasis_token {
+            options.emphasis_token = token;
+        }
+        if let Some(token) = &self.strong_token {
+            options.strong_token = token;
+        }
+        if let Some(token) = self.code_block_token {
+            options.code_block_token = token;
+        }
+        options
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+    Information,
+    Hint,
+    Off,
+}
+
+impl Severity {
+    /// The LSP severity to publish, or `None` if the check is turned off.
+    pub fn to_lsp(self) -> Option<DiagnosticSeverity> {
+        match self {
+            Severity::Error => Some(DiagnosticSeverity::ERROR),
+            Severity::Warning => Some(DiagnosticSeverity::WARNING),
+            Severity::Information => Some(DiagnosticSeverity::INFORMATION),
+            Severity::Hint => Some(DiagnosticSeverity::HINT),
+            Severity::Off => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct DiagnosticsSettings {
+    /// Links to notes that are not in the vault.
+    pub broken_link: Severity,
+    /// Links to headings or blocks that do not exist in the target note.
+    pub missing_anchor: Severity,
+    /// Unclosed or empty wiki-links.
+    pub malformed_link: Severity,
+    /// Frontmatter that is not valid YAML.
+    pub invalid_frontmatter: Severity,
+    /// Frontmatter that does not match the vault's schema.
+    pub frontmatter_schema: Severity,
+    /// `%%` lines with an unknown command or the wrong number of arguments.
+    pub invalid_command: Severity,
+    /// `%%` command arguments the vault does not agree with, such as a workspace
+    /// or template that does not exist.
+    pub command_argument: Severity,
+}
+
+impl Default for DiagnosticsSettings {
+    fn default() -> Self {
+        Self {
+            broken_link: Severity::Error,
+            missing_anchor: Severity::Warning,
+            malformed_link: Severity::Error,
+            invalid_frontmatter: Severity::Error,
+            frontmatter_schema: Severity::Warning,
+            invalid_command: Severity::Error,
+            command_argument: Severity::Warning,
+        }
+    }
+}
+
+/// Switches for individual server features; everything is on by default.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct FeatureToggles {
+    pub completion: bool,
+    pub hover: bool,
+    pub definition: bool,
+    pub references: bool,
+    pub rename: bool,
+    pub diagnostics: bool,
+    pub formatting: bool,
+    pub document_symbols: bool,
+    pub workspace_symbols: bool,
+    pub folding_ranges: bool,
+    pub semantic_tokens: bool,
+    pub document_links: bool,
+    pub code_actions: bool,
+}
+
+impl Default for FeatureToggles {
+    fn default() -> Self {
+        Self {
+            completion: true,
+            hover: true,
+            definition: true,
+            references: true,
+            rename: true,
+            diagnostics: true,
+            formatting: true,
+            document_symbols: true,
+            workspace_symbols: true,
+            folding_ranges: true,
+            semantic_tokens: true,
+            document_links: true,
+            code_actions: true,
+        }
+    }
+}
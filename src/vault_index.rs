@@ -0,0 +1,854 @@
+// src/vault_index.rs
+use notemancy_core::notes::utils::{get_title, list_all_notes};
+use pulldown_cmark::{Event, LinkType, Parser, Tag, TagEnd};
+use regex::Regex;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::sync::LazyLock;
+use tower_lsp::lsp_types::{Position, Range, Url};
+
+use crate::config::FrontmatterSchema;
+use crate::document_store::{LineIndex, line_range};
+use crate::handlers::formatting::markdown_options;
+use crate::outline::code_block_lines;
+use crate::wikilink::{anchor_matches_heading, split_anchor};
+
+static HEADING_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^\s*(#{1,6})\s+(.*)$").unwrap());
+static TAG_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?:^|\s)#([A-Za-z][\w/-]*)").unwrap());
+static TAG_TOKEN_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"#?[A-Za-z][\w/-]*").unwrap());
+static BLOCK_ID_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\s\^([A-Za-z0-9-]+)\s*$").unwrap());
+
+/// A markdown heading within a note.
+#[derive(Debug, Clone)]
+pub struct Heading {
+    pub level: usize,
+    pub text: String,
+    pub line: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkKind {
+    /// `[[path | title]]`
+    Wiki,
+    /// `[title](relative/path.md)`
+    Markdown,
+}
+
+/// An outgoing link from a note to another note in the vault.
+#[derive(Debug, Clone)]
+pub struct NoteLink {
+    /// Vault-relative path of the linked note.
+    pub target: String,
+    /// The `#heading` or `#^block-id` part of the link, without the `#`.
+    pub anchor: Option<String>,
+    pub title: Option<String>,
+    pub kind: LinkKind,
+    /// Range of the whole link.
+    pub range: Range,
+    /// The target path exactly as written inside the link (without the anchor).
+    pub written_path: String,
+    /// Range of `written_path` inside the link, `None` for anchor-only links.
+    pub target_range: Option<Range>,
+}
+
+/// A place where a note uses a tag, either `#tag` in the body or an entry of the
+/// frontmatter `tags` key.
+#[derive(Debug, Clone)]
+pub struct TagOccurrence {
+    /// The tag without the leading `#`.
+    pub name: String,
+    pub range: Range,
+}
+
+/// A `^block-id` marker at the end of a line.
+#[derive(Debug, Clone)]
+pub struct BlockId {
+    pub id: String,
+    pub line: u32,
+}
+
+/// Everything the server knows about a single note.
+#[derive(Debug, Clone)]
+pub struct NoteEntry {
+    /// Path relative to the vault directory.
+    pub path: String,
+    pub title: String,
+    pub frontmatter: Option<serde_yaml::Value>,
+    /// Alternative names from the frontmatter `aliases` key.
+    pub aliases: Vec<String>,
+    pub headings: Vec<Heading>,
+    pub links: Vec<NoteLink>,
+    pub tags: Vec<String>,
+    pub tag_occurrences: Vec<TagOccurrence>,
+    pub block_ids: Vec<BlockId>,
+    pub content: String,
+}
+
+impl NoteEntry {
+    /// Parses a note's content. The title comes from the frontmatter `title` key,
+    /// then the first H1, and finally the file name.
+    pub fn parse(path: &str, content: &str) -> Self {
+        let (frontmatter, body_start) = parse_frontmatter(content);
+        let mut headings = Vec::new();
+        let links = parse_links(path, content);
+        let mut tags = frontmatter_tags(frontmatter.as_ref());
+        let aliases = frontmatter_aliases(frontmatter.as_ref());
+        let mut tag_occurrences = frontmatter_tag_occurrences(content, &tags);
+        let mut block_ids = Vec::new();
+        let in_code = code_block_lines(content);
+
+        for (i, line) in content.lines().enumerate().skip(body_start) {
+            if in_code.get(i).copied().unwrap_or(false) {
+                continue;
+            }
+            let line_no = i as u32;
+            if let Some(caps) = HEADING_RE.captures(line) {
+                headings.push(Heading {
+                    level: caps[1].len(),
+                    text: caps[2].trim().to_string(),
+                    line: line_no,
+                });
+            } else {
+                for caps in TAG_RE.captures_iter(line) {
+                    let name = caps.get(1).unwrap();
+                    let tag = name.as_str().to_string();
+                    tag_occurrences.push(TagOccurrence {
+                        name: tag.clone(),
+                        range: line_range(line, line_no, name.start() - 1..name.end()),
+                    });
+                    if !tags.contains(&tag) {
+                        tags.push(tag);
+                    }
+                }
+            }
+            if let Some(caps) = BLOCK_ID_RE.captures(line) {
+                block_ids.push(BlockId {
+                    id: caps[1].to_string(),
+                    line: line_no,
+                });
+            }
+        }
+
+        let title = frontmatter
+            .as_ref()
+            .and_then(|fm| fm.get("title"))
+            .and_then(|t| t.as_str())
+            .map(str::to_string)
+            .or_else(|| {
+                headings
+                    .iter()
+                    .find(|h| h.level == 1)
+                    .map(|h| h.text.clone())
+            })
+            .unwrap_or_else(|| {
+                Path::new(path)
+                    .file_stem()
+                    .map(|s| s.to_string_lossy().into_owned())
+                    .unwrap_or_else(|| path.to_string())
+            });
+
+        Self {
+            path: path.to_string(),
+            title,
+            frontmatter,
+            aliases,
+            headings,
+            links,
+            tags,
+            tag_occurrences,
+            block_ids,
+            content: content.to_string(),
+        }
+    }
+
+    /// Line of the heading (`Some Heading`) or block (`^block-id`) an anchor refers to.
+    pub fn anchor_line(&self, anchor: &str) -> Option<u32> {
+        match anchor.strip_prefix('^') {
+            Some(block_id) => self
+                .block_ids
+                .iter()
+                .find(|b| b.id == block_id)
+                .map(|b| b.line),
+            None => self
+                .headings
+                .iter()
+                .find(|h| anchor_matches_heading(anchor, &h.text))
+                .map(|h| h.line),
+        }
+    }
+
+    /// The part of the note an anchor refers to: a heading and everything up to the
+    /// next heading of the same or higher level, or the paragraph ending in a block ID.
+    pub fn anchor_section(&self, anchor: &str) -> Option<String> {
+        let line = self.anchor_line(anchor)? as usize;
+        let lines: Vec<&str> = self.content.lines().collect();
+        let (start, end) = if anchor.starts_with('^') {
+            let start = lines[..line]
+                .iter()
+                .rposition(|l| l.trim().is_empty())
+                .map(|i| i + 1)
+                .unwrap_or(0);
+            (start, line + 1)
+        } else {
+            let level = self
+                .headings
+                .iter()
+                .find(|h| h.line as usize == line)?
+                .level;
+            let end = self
+                .headings
+                .iter()
+                .find(|h| h.line as usize > line && h.level <= level)
+                .map(|h| h.line as usize)
+                .unwrap_or(lines.len());
+            (line, end)
+        };
+        Some(lines[start..end.min(lines.len())].join("\n"))
+    }
+    /// The frontmatter `summary` (or `description`) of the note.
+    pub fn summary(&self) -> Option<&str> {
+        let frontmatter = self.frontmatter.as_ref()?;
+        frontmatter
+            .get("summary")
+            .or_else(|| frontmatter.get("description"))
+            .and_then(|s| s.as_str())
+    }
+
+    /// The first paragraph of the body, skipping the frontmatter and headings.
+    pub fn first_paragraph(&self) -> Option<String> {
+        let (_, body_start) = parse_frontmatter(&self.content);
+        let paragraph: Vec<&str> = self
+            .content
+            .lines()
+            .skip(body_start)
+            .skip_while(|l| l.trim().is_empty() || HEADING_RE.is_match(l))
+            .take_while(|l| !l.trim().is_empty() && !HEADING_RE.is_match(l))
+            .collect();
+        (!paragraph.is_empty()).then(|| paragraph.join("\n"))
+    }
+}
+
+/// The link being parsed by `parse_links`.
+struct PendingLink {
+    kind: LinkKind,
+    dest: String,
+    span: std::ops::Range<usize>,
+    /// The span holding the destination: the link itself, or its definition.
+    dest_span: Option<std::ops::Range<usize>>,
+    title: String,
+}
+
+/// Extracts wiki-links and relative markdown links to other notes, using the same
+/// pulldown-cmark parse as the formatter's `WikiLinkTransformer`. The target range
+/// of a reference-style link is the destination in its `[label]: path.md` definition.
+fn parse_links(path: &str, content: &str) -> Vec<NoteLink> {
+    let line_index = LineIndex::new(content);
+    let mut links = Vec::new();
+    let mut pending: Option<PendingLink> = None;
+
+    let mut events = Parser::new_ext(content, markdown_options()).into_offset_iter();
+    while let Some((event, span)) = events.next() {
+        match event {
+            Event::Start(Tag::Link {
+                link_type,
+                dest_url,
+                id,
+                ..
+            }) => {
+                let kind = match link_type {
+                    LinkType::WikiLink { .. } => LinkKind::Wiki,
+                    _ => LinkKind::Markdown,
+                };
+                let dest_span = match link_type {
+                    LinkType::Reference | LinkType::Collapsed | LinkType::Shortcut => events
+                        .reference_definitions()
+                        .get(&id)
+                        .map(|definition| definition.span.clone()),
+                    _ => Some(span.clone()),
+                };
+                pending = Some(PendingLink {
+                    kind,
+                    dest: dest_url.to_string(),
+                    span,
+                    dest_span,
+                    title: String::new(),
+                });
+            }
+            Event::Text(text) | Event::Code(text) => {
+                if let Some(link) = pending.as_mut() {
+                    link.title.push_str(&text);
+                }
+            }
+            Event::End(TagEnd::Link) => {
+                let Some(PendingLink {
+                    kind,
+                    dest,
+                    span,
+                    dest_span,
+                    title,
+                }) = pending.take()
+                else {
+                    continue;
+                };
+                let Some((target, anchor)) = resolve_link_target(path, &dest, kind) else {
+                    continue;
+                };
+                let (written_path, _) = split_anchor(dest.trim());
+                let target_range =
+                    dest_span
+                        .filter(|_| !written_path.is_empty())
+                        .and_then(|dest_span| {
+                            let source = &content[dest_span.clone()];
+                            let offset = match kind {
+                                LinkKind::Wiki => source.find(written_path)?,
+                                LinkKind::Markdown if dest_span != span => {
+                                    // A definition: the destination follows the label.
+                                    let label_end = source.find("]:")? + 2;
+                                    label_end + source[label_end..].find(dest.trim())?
+                                }
+                                LinkKind::Markdown => source.rfind(dest.trim())?,
+                            };
+                            let start = dest_span.start + offset;
+                            Some(line_index.range(start..start + written_path.len()))
+                        });
+                let title = title.trim().to_string();
+                links.push(NoteLink {
+                    target,
+                    anchor,
+                    title: (!title.is_empty() && title != dest.trim()).then_some(title),
+                    kind,
+                    range: line_index.range(span),
+                    written_path: written_path.to_string(),
+                    target_range,
+                });
+            }
+            _ => {}
+        }
+    }
+    links
+}
+
+/// Turns a link destination into a vault-relative note path and optional anchor.
+/// Wiki-links are relative to the vault root; markdown links are relative to the
+/// linking note and percent-decoded. External URLs and links to non-markdown files yield `None`.
+fn resolve_link_target(
+    source: &str,
+    dest: &str,
+    kind: LinkKind,
+) -> Option<(String, Option<String>)> {
+    let (dest_path, anchor) = split_anchor(dest);
+    let anchor = anchor.filter(|a| !a.is_empty()).map(str::to_string);
+    if dest_path.is_empty() {
+        // `[[#Heading]]` and `[text](#heading)` point into the note itself.
+        return Some((source.to_string(), anchor));
+    }
+    let target = match kind {
+        LinkKind::Wiki => {
+            let path = dest_path.trim_start_matches("./");
+            if Path::new(path).extension().is_some() {
+                path.to_string()
+            } else {
+                format!("{}.md", path)
+            }
+        }
+        LinkKind::Markdown => {
+            if dest_path.contains("://") || dest_path.starts_with("mailto:") {
+                return None;
+            }
+            let dest_path = percent_decode(dest_path);
+            let base = match dest_path.strip_prefix('/') {
+                Some(_) => Path::new(""),
+                None => Path::new(source).parent().unwrap_or(Path::new("")),
+            };
+            let joined = normalize_relative(&base.join(dest_path.trim_start_matches('/')))?;
+            if !joined.ends_with(".md") {
+                return None;
+            }
+            joined
+        }
+    };
+    Some((target, anchor))
+}
+
+/// Decodes `%XX` escapes in a markdown link destination, e.g. `My%20Note.md`.
+/// Destinations that do not decode to UTF-8 are returned as written.
+pub fn percent_decode(dest: &str) -> String {
+    let bytes = dest.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| dest.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8(decoded).unwrap_or_else(|_| dest.to_string())
+}
+
+/// Lexically resolves `.` and `..` in a vault-relative path. Returns `None` if the
+/// path escapes the vault.
+pub fn normalize_relative(path: &Path) -> Option<String> {
+    let mut parts: Vec<String> = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => parts.push(part.to_string_lossy().into_owned()),
+            Component::ParentDir => {
+                parts.pop()?;
+            }
+            Component::CurDir => {}
+            Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    Some(parts.join("/"))
+}
+
+/// Returns the parsed YAML frontmatter (if any) and the first line after it.
+fn parse_frontmatter(content: &str) -> (Option<serde_yaml::Value>, usize) {
+    let mut lines = content.lines();
+    if lines.next().map(str::trim_end) != Some("---") {
+        return (None, 0);
+    }
+    let mut yaml = String::new();
+    for (i, line) in lines.enumerate() {
+        if line.trim_end() == "---" {
+            return (serde_yaml::from_str(&yaml).ok(), i + 2);
+        }
+        yaml.push_str(line);
+        yaml.push('\n');
+    }
+    (None, 0)
+}
+
+/// Tags declared in the frontmatter, either as a list or a single string.
+fn frontmatter_tags(frontmatter: Option<&serde_yaml::Value>) -> Vec<String> {
+    match frontmatter.and_then(|fm| fm.get("tags")) {
+        Some(serde_yaml::Value::Sequence(seq)) => seq
+            .iter()
+            .filter_map(|t| t.as_str())
+            .map(|t| t.trim_start_matches('#').to_string())
+            .collect(),
+        Some(serde_yaml::Value::String(s)) => s
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|t| !t.is_empty())
+            .map(|t| t.trim_start_matches('#').to_string())
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Lines of the frontmatter `tags` value, as (line number, byte offset where the
+/// value starts). Covers `tags: a, b`, `tags: [a, b]` and block lists below `tags:`.
+pub fn frontmatter_tag_lines(content: &str) -> Vec<(usize, usize)> {
+    let (_, body_start) = parse_frontmatter(content);
+    let mut lines = Vec::new();
+    let mut in_tags = false;
+    // The frontmatter sits between the opening `---` and the line before the body.
+    for (i, line) in content
+        .lines()
+        .enumerate()
+        .take(body_start.saturating_sub(1))
+        .skip(1)
+    {
+        if let Some(rest) = line.strip_prefix("tags:") {
+            in_tags = true;
+            lines.push((i, line.len() - rest.len()));
+        } else if in_tags && (line.starts_with(' ') || line.starts_with('-')) {
+            lines.push((i, 0));
+        } else {
+            in_tags = false;
+        }
+    }
+    lines
+}
+
+fn frontmatter_tag_occurrences(content: &str, tags: &[String]) -> Vec<TagOccurrence> {
+    let lines: Vec<&str> = content.lines().collect();
+    let mut occurrences = Vec::new();
+    for (i, value_start) in frontmatter_tag_lines(content) {
+        let line = lines[i];
+        for m in TAG_TOKEN_RE.find_iter(&line[value_start..]) {
+            let name = m.as_str().trim_start_matches('#');
+            if tags.iter().any(|t| t == name) {
+                occurrences.push(TagOccurrence {
+                    name: name.to_string(),
+                    range: line_range(
+                        line,
+                        i as u32,
+                        value_start + m.start()..value_start + m.end(),
+                    ),
+                });
+            }
+        }
+    }
+    occurrences
+}
+
+/// Reads `aliases` as either a list or a single string.
+fn frontmatter_aliases(frontmatter: Option<&serde_yaml::Value>) -> Vec<String> {
+    match frontmatter.and_then(|fm| fm.get("aliases")) {
+        Some(serde_yaml::Value::Sequence(seq)) => seq
+            .iter()
+            .filter_map(|a| a.as_str())
+            .map(str::to_string)
+            .collect(),
+        Some(serde_yaml::Value::String(s)) => vec![s.clone()],
+        _ => Vec::new(),
+    }
+}
+
+/// A note read from disk, so the read can happen without holding the index.
+#[derive(Debug)]
+pub struct DiskNote {
+    path: PathBuf,
+    // Contents and the title notemancy-core resolves; `None` if it could not be read.
+    content: Option<(String, Option<String>)>,
+}
+
+impl DiskNote {
+    /// Reads a markdown note and its title. This blocks, so async callers run it
+    /// on the blocking pool.
+    pub fn read(path: PathBuf) -> Self {
+        let content = if path.extension().and_then(|e| e.to_str()) == Some("md") {
+            fs::read_to_string(&path)
+                .ok()
+                .map(|content| (content, get_title(&path).ok()))
+        } else {
+            None
+        };
+        Self { path, content }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// In-memory index of every note in a vault. It is built once when the server
+/// starts and queried by the handlers instead of reading notes from disk.
+#[derive(Debug, Default)]
+pub struct VaultIndex {
+    name: String,
+    root: PathBuf,
+    notes: BTreeMap<String, NoteEntry>,
+    // Reverse-link map: target note path -> notes linking to it.
+    backlinks: HashMap<String, BTreeSet<String>>,
+    // Lowercased frontmatter alias -> path of the note declaring it.
+    aliases: HashMap<String, String>,
+    // Frontmatter schema from the vault's config entry.
+    schema: FrontmatterSchema,
+}
+
+impl VaultIndex {
+    /// Reads and parses every markdown note in the vault directory.
+    pub fn build(name: String, root: PathBuf) -> Result<Self, String> {
+        let note_paths = list_all_notes(&root, true).map_err(|e| e.to_string())?;
+        let mut index = Self {
+            name,
+            root,
+            ..Default::default()
+        };
+        for note in note_paths {
+            let full_path = index.root.join(&note);
+            let content = match fs::read_to_string(&full_path) {
+                Ok(c) => c,
+                Err(_) => continue, // Skip notes that cannot be read.
+            };
+            let mut entry = NoteEntry::parse(&note, &content);
+            if let Ok(title) = get_title(&full_path) {
+                entry.title = title;
+            }
+            index.insert_entry(entry);
+        }
+        Ok(index)
+    }
+
+    /// An empty index of the vault at `root`, filled by `update_note` in tests.
+    #[cfg(test)]
+    pub fn empty(root: PathBuf) -> Self {
+        Self {
+            root,
+            ..Default::default()
+        }
+    }
+
+    /// Adds or replaces a note, keeping the reverse-link map in sync.
+    fn insert_entry(&mut self, entry: NoteEntry) {
+        self.remove_entry(&entry.path.clone());
+        for link in &entry.links {
+            self.backlinks
+                .entry(link.target.clone())
+                .or_default()
+                .insert(entry.path.clone());
+        }
+        for alias in &entry.aliases {
+            self.aliases
+                .insert(alias.to_lowercase(), entry.path.clone());
+        }
+        self.notes.insert(entry.path.clone(), entry);
+    }
+
+    fn remove_entry(&mut self, path: &str) {
+        let Some(old) = self.notes.remove(path) else {
+            return;
+        };
+        for link in &old.links {
+            if let Some(sources) = self.backlinks.get_mut(&link.target) {
+                sources.remove(path);
+                if sources.is_empty() {
+                    self.backlinks.remove(&link.target);
+                }
+            }
+        }
+        for alias in &old.aliases {
+            let alias = alias.to_lowercase();
+            if self.aliases.get(&alias).is_some_and(|p| p == path) {
+                self.aliases.remove(&alias);
+            }
+        }
+    }
+
+    /// Returns every link in the vault that points at the given note, directly or
+    /// through one of its aliases, together with the note containing it.
+    pub fn backlinks(&self, target: &str) -> Vec<(&NoteEntry, &NoteLink)> {
+        let mut sources: BTreeSet<&String> =
+            self.backlinks.get(target).into_iter().flatten().collect();
+        // Links through an alias are keyed by the alias as written, in any case.
+        if self
+            .notes
+            .get(target)
+            .is_some_and(|note| !note.aliases.is_empty())
+        {
+            for (key, linking) in &self.backlinks {
+                let alias = key.strip_suffix(".md").unwrap_or(key).to_lowercase();
+                if self.aliases.get(&alias).is_some_and(|path| path == target) {
+                    sources.extend(linking);
+                }
+            }
+        }
+        sources
+            .into_iter()
+            .filter_map(|source| self.notes.get(source))
+            .flat_map(|note| {
+                note.links
+                    .iter()
+                    .filter(move |link| self.links_to(link, target))
+                    .map(move |link| (note, link))
+            })
+            .collect()
+    }
+
+    /// Whether a link points at the note at `target`, by its path or, for a
+    /// wiki-link to no note path, by one of its aliases.
+    fn links_to(&self, link: &NoteLink, target: &str) -> bool {
+        link.target == target
+            || (link.kind == LinkKind::Wiki
+                && !self.notes.contains_key(&link.target)
+                && self
+                    .resolve(&link.written_path)
+                    .is_some_and(|note| note.path == target))
+    }
+
+    /// Every tag used in the vault with the number of notes using it.
+    pub fn tags(&self) -> BTreeMap<&str, usize> {
+        let mut counts = BTreeMap::new();
+        for tag in self.notes.values().flat_map(|note| &note.tags) {
+            *counts.entry(tag.as_str()).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    /// Every place a tag is used in the vault, together with the note using it.
+    pub fn tag_occurrences(&self, tag: &str) -> Vec<(&NoteEntry, &TagOccurrence)> {
+        self.notes
+            .values()
+            .flat_map(|note| {
+                note.tag_occurrences
+                    .iter()
+                    .filter(move |t| t.name == tag)
+                    .map(move |t| (note, t))
+            })
+            .collect()
+    }
+
+    pub fn set_schema(&mut self, schema: FrontmatterSchema) {
+        self.schema = schema;
+    }
+
+    pub fn schema(&self) -> &FrontmatterSchema {
+        &self.schema
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn notes(&self) -> impl Iterator<Item = &NoteEntry> {
+        self.notes.values()
+    }
+
+    pub fn get(&self, path: &str) -> Option<&NoteEntry> {
+        self.notes.get(path)
+    }
+
+    /// Resolves a link target to a note, accepting the path with or without `.md`,
+    /// or one of the note's frontmatter aliases (case-insensitive).
+    pub fn resolve(&self, target: &str) -> Option<&NoteEntry> {
+        let target = target.trim().trim_start_matches("./");
+        self.notes
+            .get(target)
+            .or_else(|| self.notes.get(&format!("{}.md", target)))
+            .or_else(|| {
+                let path = self.aliases.get(&target.to_lowercase())?;
+                self.notes.get(path)
+            })
+    }
+
+    /// Re-parses a note from the given content (e.g. an unsaved editor buffer).
+    pub fn update_note(&mut self, path: &str, content: &str) {
+        let mut entry = NoteEntry::parse(path, content);
+        // Keep the title notemancy-core resolved unless the note now declares its own.
+        if let Some(old) = self.notes.get(path) {
+            if entry
+                .frontmatter
+                .as_ref()
+                .and_then(|fm| fm.get("title"))
+                .is_none()
+                && !entry.headings.iter().any(|h| h.level == 1)
+            {
+                entry.title = old.title.clone();
+            }
+        }
+        self.insert_entry(entry);
+    }
+
+    /// Puts a note read by `DiskNote::read` into the index, or drops it if it could
+    /// not be read. Paths outside the vault or that are not markdown files are ignored.
+    pub fn apply_disk_note(&mut self, note: DiskNote) {
+        let Some(relative) = self.markdown_relative_path(&note.path) else {
+            return;
+        };
+        match note.content {
+            Some((content, title)) => {
+                let mut entry = NoteEntry::parse(&relative, &content);
+                if let Some(title) = title {
+                    entry.title = title;
+                }
+                self.insert_entry(entry);
+            }
+            None => {
+                self.remove_entry(&relative);
+            }
+        }
+    }
+
+    /// Drops a note (or every note below a directory) that was deleted or moved away.
+    pub fn remove_path(&mut self, abs_path: &Path) {
+        let Some(relative) = self.relative_path(abs_path) else {
+            return;
+        };
+        let dir_prefix = format!("{}/", relative.trim_end_matches('/'));
+        let removed: Vec<String> = self
+            .notes
+            .keys()
+            .filter(|path| **path == relative || path.starts_with(&dir_prefix))
+            .cloned()
+            .collect();
+        for path in removed {
+            self.remove_entry(&path);
+        }
+    }
+
+    fn markdown_relative_path(&self, abs_path: &Path) -> Option<String> {
+        if abs_path.extension().and_then(|e| e.to_str()) != Some("md") {
+            return None;
+        }
+        self.relative_path(abs_path)
+    }
+
+    /// Returns the vault-relative path of an absolute path inside the vault.
+    pub fn relative_path(&self, abs_path: &Path) -> Option<String> {
+        abs_path
+            .strip_prefix(&self.root)
+            .ok()
+            .map(|p| p.to_string_lossy().replace('\\', "/"))
+    }
+
+    /// Returns the file URI of a vault-relative note path.
+    pub fn note_uri(&self, path: &str) -> Option<Url> {
+        Url::from_file_path(self.root.join(path)).ok()
+    }
+}
+
+/// The vault indexes open in this session, keyed by vault name.
+#[derive(Debug, Default)]
+pub struct VaultSet {
+    vaults: BTreeMap<String, VaultIndex>,
+    // Vault used for documents that live outside every open vault.
+    fallback: Option<String>,
+}
+
+impl VaultSet {
+    pub fn insert(&mut self, index: VaultIndex) {
+        self.vaults.insert(index.name().to_string(), index);
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.vaults.contains_key(name)
+    }
+
+    pub fn set_fallback(&mut self, name: String) {
+        self.fallback = Some(name);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &VaultIndex> {
+        self.vaults.values()
+    }
+
+    /// Returns the open vault whose directory contains `path` (the innermost one
+    /// if vaults are nested), or the fallback vault.
+    pub fn for_path(&self, path: &Path) -> Option<&VaultIndex> {
+        self.containing(path).or_else(|| self.fallback())
+    }
+
+    pub fn fallback(&self) -> Option<&VaultIndex> {
+        self.vaults.get(self.fallback.as_ref()?)
+    }
+
+    pub fn for_uri(&self, uri: &Url) -> Option<&VaultIndex> {
+        match uri.to_file_path() {
+            Ok(path) => self.for_path(&path),
+            Err(()) => self.fallback(),
+        }
+    }
+
+    /// Like `for_path`, but only returns a vault that actually contains `path`.
+    pub fn containing_mut(&mut self, path: &Path) -> Option<&mut VaultIndex> {
+        let name = self.containing(path)?.name().to_string();
+        self.vaults.get_mut(&name)
+    }
+
+    /// Returns the open vault that contains `path`, without falling back.
+    pub fn containing(&self, path: &Path) -> Option<&VaultIndex> {
+        self.vaults
+            .values()
+            .filter(|v| path.starts_with(v.root()))
+            .max_by_key(|v| v.root().components().count())
+    }
+}
@@ -0,0 +1,189 @@
+// src/watcher.rs
+use notify::event::{ModifyKind, RenameMode};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::{RwLock, mpsc};
+use tower_lsp::lsp_types::{FileChangeType, FileEvent, Url};
+
+use crate::document_store::DocumentStore;
+use crate::vault_index::{DiskNote, VaultSet};
+
+/// A file-system change relevant to the vault index.
+#[derive(Debug, Clone)]
+pub enum WatchEvent {
+    /// A file was created or modified (or renamed to this path).
+    Changed(PathBuf),
+    /// A file was deleted (or renamed away from this path).
+    Removed(PathBuf),
+}
+
+impl WatchEvent {
+    /// Converts a `workspace/didChangeWatchedFiles` event sent by the client.
+    pub fn from_file_event(event: &FileEvent) -> Option<Self> {
+        let path = event.uri.to_file_path().ok()?;
+        if event.typ == FileChangeType::DELETED {
+            Some(WatchEvent::Removed(path))
+        } else {
+            Some(WatchEvent::Changed(path))
+        }
+    }
+
+    /// Converts an event reported by the internal notify watcher.
+    fn from_notify(event: notify::Event) -> Vec<Self> {
+        let paths = event.paths;
+        match event.kind {
+            EventKind::Create(_) => paths.into_iter().map(WatchEvent::Changed).collect(),
+            EventKind::Remove(_) => paths.into_iter().map(WatchEvent::Removed).collect(),
+            EventKind::Modify(ModifyKind::Name(RenameMode::From)) => {
+                paths.into_iter().map(WatchEvent::Removed).collect()
+            }
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)) if paths.len() == 2 => vec![
+                WatchEvent::Removed(paths[0].clone()),
+                WatchEvent::Changed(paths[1].clone()),
+            ],
+            EventKind::Modify(_) => paths.into_iter().map(WatchEvent::Changed).collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// A watch event with the notes it touches read from disk.
+enum DiskChange {
+    Read(DiskNote),
+    Removed(PathBuf),
+}
+
+/// Applies file-system changes to the index of the vault containing each path.
+/// Modifications to documents that are open in the editor are skipped, since the
+/// editor buffer is the source of truth.
+pub async fn apply_events(
+    events: Vec<WatchEvent>,
+    vaults: &RwLock<VaultSet>,
+    documents: &RwLock<DocumentStore>,
+) {
+    // Copy out the open documents instead of holding both locks: the server takes
+    // `vaults` before `documents`.
+    let open: HashSet<Url> = documents.read().await.uris().into_iter().collect();
+    // Read the notes before taking the lock, so requests are not held up while a
+    // `git checkout` rewrites the vault.
+    let Ok(changes) = tokio::task::spawn_blocking(move || read_events(events, &open)).await else {
+        return;
+    };
+    let mut vaults = vaults.write().await;
+    for change in changes {
+        match change {
+            DiskChange::Read(note) => {
+                if let Some(index) = vaults.containing_mut(note.path()) {
+                    index.apply_disk_note(note);
+                }
+            }
+            DiskChange::Removed(path) => {
+                if let Some(index) = vaults.containing_mut(&path) {
+                    index.remove_path(&path);
+                }
+            }
+        }
+    }
+}
+
+/// Reads the notes changed by each event, skipping those open in the editor.
+fn read_events(events: Vec<WatchEvent>, open: &HashSet<Url>) -> Vec<DiskChange> {
+    let mut changes = Vec::new();
+    for event in events {
+        match event {
+            WatchEvent::Changed(path) => {
+                // A directory created or moved into the vault brings its notes along.
+                let paths = if path.is_dir() {
+                    markdown_files(&path)
+                } else {
+                    vec![path]
+                };
+                for path in paths {
+                    let is_open = Url::from_file_path(&path)
+                        .map(|uri| open.contains(&uri))
+                        .unwrap_or(false);
+                    if !is_open {
+                        changes.push(DiskChange::Read(DiskNote::read(path)));
+                    }
+                }
+            }
+            WatchEvent::Removed(path) => changes.push(DiskChange::Removed(path)),
+        }
+    }
+    changes
+}
+
+/// Markdown files below a directory, skipping hidden directories.
+fn markdown_files(dir: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let Ok(entries) = fs::read_dir(dir) else {
+        return files;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            if !entry.file_name().to_string_lossy().starts_with('.') {
+                files.extend(markdown_files(&path));
+            }
+        } else if path.extension().is_some_and(|ext| ext == "md") {
+            files.push(path);
+        }
+    }
+    files
+}
+
+/// Starts an internal watcher on a vault directory for clients that cannot
+/// register `workspace/didChangeWatchedFiles`. The returned watcher must be kept
+/// alive for as long as events should be delivered; the receiver is signalled
+/// after each batch of events is applied to the index.
+pub fn spawn_fs_watcher(
+    root: &Path,
+    vaults: Arc<RwLock<VaultSet>>,
+    documents: Arc<RwLock<DocumentStore>>,
+) -> notify::Result<(RecommendedWatcher, mpsc::UnboundedReceiver<()>)> {
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let (applied_tx, applied_rx) = mpsc::unbounded_channel();
+    let mut watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+        if let Ok(event) = res {
+            let events = WatchEvent::from_notify(event);
+            if !events.is_empty() {
+                let _ = tx.send(events);
+            }
+        }
+    })?;
+    watcher.watch(root, RecursiveMode::Recursive)?;
+
+    tokio::spawn(async move {
+        while let Some(events) = rx.recv().await {
+            apply_events(events, &vaults, &documents).await;
+            if applied_tx.send(()).is_err() {
+                break;
+            }
+        }
+    });
+
+    Ok((watcher, applied_rx))
+}
+
+/// Watches a single file and signals every change to it. The parent directory is
+/// watched rather than the file, so editors that save by replacing the file are
+/// still noticed.
+pub fn spawn_file_watcher(
+    path: &Path,
+) -> notify::Result<(RecommendedWatcher, mpsc::UnboundedReceiver<()>)> {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let target = path.to_path_buf();
+    let mut watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+        if let Ok(event) = res {
+            if event.paths.iter().any(|p| p == &target) {
+                let _ = tx.send(());
+            }
+        }
+    })?;
+    let dir = path.parent().unwrap_or(path);
+    watcher.watch(dir, RecursiveMode::NonRecursive)?;
+    Ok((watcher, rx))
+}
@@ -0,0 +1,76 @@
+// src/wikilink.rs
+use regex::Regex;
+use std::sync::LazyLock;
+
+/// Matches a wiki-link of the form `[[ relative_path | title ]]`, where whitespace
+/// and the title are optional.
+static WIKILINK_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"\[\[\s*(?P<path>[^|\]]+?)\s*(?:\|\s*(?P<title>[^\]]+?)\s*)?\]\]").unwrap()
+});
+
+/// A wiki-link found on a single line. All offsets are byte offsets into that line.
+#[derive(Debug, Clone)]
+pub struct WikiLink<'a> {
+    /// Start of the opening `[[`.
+    pub start: usize,
+    /// End of the closing `]]`.
+    pub end: usize,
+    pub path: &'a str,
+    pub path_start: usize,
+    pub path_end: usize,
+    pub title: Option<&'a str>,
+}
+
+/// Returns every wiki-link on the given line, in order of appearance.
+pub fn find_wikilinks(line: &str) -> Vec<WikiLink<'_>> {
+    WIKILINK_RE
+        .captures_iter(line)
+        .filter_map(|caps| {
+            let mat = caps.get(0)?;
+            let path = caps.name("path")?;
+            Some(WikiLink {
+                start: mat.start(),
+                end: mat.end(),
+                path: path.as_str(),
+                path_start: path.start(),
+                path_end: path.end(),
+                title: caps.name("title").map(|t| t.as_str()),
+            })
+        })
+        .collect()
+}
+
+/// Returns the wiki-link on the line that contains the given byte offset, if any.
+pub fn wikilink_at(line: &str, byte: usize) -> Option<WikiLink<'_>> {
+    find_wikilinks(line)
+        .into_iter()
+        .find(|link| byte >= link.start && byte <= link.end)
+}
+
+/// Splits a link target into the note path and an optional `#heading` or `#^block-id` anchor.
+pub fn split_anchor(target: &str) -> (&str, Option<&str>) {
+    match target.split_once('#') {
+        Some((path, anchor)) => (path.trim(), Some(anchor.trim())),
+        None => (target.trim(), None),
+    }
+}
+
+/// Compares a link anchor against a heading, accepting both the literal heading
+/// text (`[[note#My Heading]]`) and its slug (`[text](note.md#my-heading)`).
+pub fn anchor_matches_heading(anchor: &str, heading: &str) -> bool {
+    anchor.eq_ignore_ascii_case(heading) || anchor.eq_ignore_ascii_case(&heading_slug(heading))
+}
+
+/// GitHub-style slug of a heading: lowercase, spaces to dashes, punctuation dropped.
+pub fn heading_slug(heading: &str) -> String {
+    heading
+        .trim()
+        .to_lowercase()
+        .chars()
+        .filter_map(|c| match c {
+            ' ' => Some('-'),
+            c if c.is_alphanumeric() || c == '-' || c == '_' => Some(c),
+            _ => None,
+        })
+        .collect()
+}